@@ -0,0 +1,37 @@
+#!/bin/sh
+# Stand-in UCI engine for trying the GUI and running the tests without a real engine.
+# It reports the same short line for every position.
+
+searching=0
+while read -r line; do
+    case "$line" in
+        uci)
+            echo "id name MockEngine"
+            echo "id author ogronman-chess-gui"
+            echo "uciok"
+            ;;
+        isready)
+            echo "readyok"
+            ;;
+        "go infinite"*)
+            echo "info depth 1 score cp 20 pv e2e4"
+            echo "info depth 2 score cp 35 pv e2e4 e7e5"
+            echo "info depth 3 score cp 30 pv e2e4 e7e5 g1f3"
+            searching=1
+            ;;
+        go*)
+            echo "info depth 1 score cp 20 pv e2e4"
+            echo "bestmove e2e4"
+            ;;
+        stop)
+            # Only an infinite search is still running when told to stop.
+            if [ "$searching" = 1 ]; then
+                echo "bestmove e2e4"
+                searching=0
+            fi
+            ;;
+        quit)
+            exit 0
+            ;;
+    esac
+done
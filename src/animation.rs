@@ -0,0 +1,90 @@
+//! Move animations. The board before and after a change is compared: pieces
+//! that moved slide from their old square to their new one, so castling moves
+//! both king and rook, and captured pieces fade out.
+
+use std::time::Duration;
+
+/// Changes touching more squares, such as a new game, are shown at once.
+const MAX_CHANGED_SQUARES: usize = 12;
+
+/// A piece sliding between two squares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slide {
+    /// Colour and piece as it looked before the move, so a promoting pawn slides as a pawn.
+    pub piece: (u8, u8),
+    pub from: u8,
+    pub to: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub slides: Vec<Slide>,
+    /// Captured pieces with the square they fade out on.
+    pub fades: Vec<(u8, (u8, u8))>,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl Animation {
+    /// Animation from `before` to `after`, boards of [colour, piece] per square, or None if
+    /// nothing moved or too much changed.
+    pub fn between(before: &[Vec<u8>], after: &[Vec<u8>], duration: Duration) -> Option<Animation> {
+        if duration == Duration::from_secs(0) {
+            return None;
+        }
+        let changed: Vec<u8> = (0..64u8).filter(|&sq| before[sq as usize] != after[sq as usize]).collect();
+        if changed.is_empty() || changed.len() > MAX_CHANGED_SQUARES {
+            return None;
+        }
+        let piece = |board: &[Vec<u8>], sq: u8| (board[sq as usize][0], board[sq as usize][1]);
+        let mut vacated: Vec<u8> = changed.iter().cloned().filter(|&sq| before[sq as usize][0] != 0).collect();
+        let arrived: Vec<u8> = changed.iter().cloned().filter(|&sq| after[sq as usize][0] != 0).collect();
+
+        let mut slides = Vec::new();
+        let mut fades = Vec::new();
+        for &to in arrived.iter() {
+            let (colour, kind) = piece(after, to);
+            // The same piece, or for a promotion a pawn of the same colour.
+            let found = vacated.iter().position(|&from| piece(before, from) == (colour, kind))
+                .or_else(|| vacated.iter().position(|&from| piece(before, from) == (colour, 1)));
+            if let Some(index) = found {
+                let from = vacated.remove(index);
+                if from != to {
+                    slides.push(Slide { piece: piece(before, from), from, to });
+                }
+            }
+            if before[to as usize][0] != 0 && before[to as usize][0] != colour {
+                fades.push((to, piece(before, to)));
+                vacated.retain(|&sq| sq != to);
+            }
+        }
+        // Whatever left its square without arriving anywhere was captured, e.g. en passant.
+        for &sq in vacated.iter() {
+            fades.push((sq, piece(before, sq)));
+        }
+
+        if slides.is_empty() && fades.is_empty() {
+            return None;
+        }
+        Some(Animation { slides, fades, elapsed: Duration::from_secs(0), duration })
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        self.elapsed += delta;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Eased progress from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Whether a piece is still sliding onto `sq`, so it is not drawn there yet.
+    pub fn is_arriving(&self, sq: u8) -> bool {
+        self.slides.iter().any(|slide| slide.to == sq)
+    }
+}
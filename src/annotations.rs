@@ -0,0 +1,54 @@
+//! Arrows and circled squares drawn on a position, stored the way PGN
+//! comments encode them (`[%cal Ge2e4]`, `[%csl Rd5]`).
+
+use crate::rules;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    /// `(colour, from, to)`
+    pub arrows: Vec<(char, u8, u8)>,
+    /// `(colour, square)`
+    pub circles: Vec<(char, u8)>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.circles.is_empty()
+    }
+
+    /// Adds the arrow, replaces its colour, or removes it if it already has that colour.
+    pub fn toggle_arrow(&mut self, colour: char, from: u8, to: u8) {
+        match self.arrows.iter().position(|arrow| arrow.1 == from && arrow.2 == to) {
+            Some(i) if self.arrows[i].0 == colour => { self.arrows.remove(i); }
+            Some(i) => self.arrows[i].0 = colour,
+            None => self.arrows.push((colour, from, to)),
+        }
+    }
+
+    /// Adds the circle, replaces its colour, or removes it if it already has that colour.
+    pub fn toggle_circle(&mut self, colour: char, square: u8) {
+        match self.circles.iter().position(|circle| circle.1 == square) {
+            Some(i) if self.circles[i].0 == colour => { self.circles.remove(i); }
+            Some(i) => self.circles[i].0 = colour,
+            None => self.circles.push((colour, square)),
+        }
+    }
+
+    /// PGN comment body such as `[%csl Gd4][%cal Ge2e4,Rg1f3]`, empty if there is nothing to write.
+    pub fn to_pgn_comment(&self) -> String {
+        let mut comment = String::new();
+        if !self.circles.is_empty() {
+            let circles: Vec<String> = self.circles.iter()
+                .map(|(colour, square)| format!("{}{}", colour, rules::square_name(*square)))
+                .collect();
+            comment.push_str(&format!("[%csl {}]", circles.join(",")));
+        }
+        if !self.arrows.is_empty() {
+            let arrows: Vec<String> = self.arrows.iter()
+                .map(|(colour, from, to)| format!("{}{}{}", colour, rules::square_name(*from), rules::square_name(*to)))
+                .collect();
+            comment.push_str(&format!("[%cal {}]", arrows.join(",")));
+        }
+        comment
+    }
+}
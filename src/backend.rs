@@ -0,0 +1,95 @@
+//! Rules backends for standard games. The GUI talks to the rules through
+//! `ChessBackend`, so another group's chess crate can be dropped in by
+//! implementing it. `RulesBackend` uses the in-tree `rules` module, with
+//! moves from `Position::legal_moves` or the bitboard generator.
+
+use crate::bitboard;
+use crate::rules::{self, Move, Position};
+
+/// Squares are named as in "e2" and pieces are `rules` piece codes.
+pub trait ChessBackend {
+    /// Moves the piece on `from` to `to`. Returns false if the move is illegal.
+    /// A promoting pawn becomes a queen until `set_promotion` is called.
+    fn make_move(&mut self, from: &str, to: &str) -> bool;
+    /// Squares the piece on `square` can move to.
+    fn get_possible_moves(&self, square: &str) -> Vec<String>;
+    /// Chooses the piece the pawn that just promoted on `square` becomes.
+    fn set_promotion(&mut self, square: &str, piece: u8);
+    fn get_fen(&self) -> String;
+    /// Game state as shown in the side panel, e.g. "Check".
+    fn get_game_state(&self) -> String;
+}
+
+/// Backend names accepted by `by_name`.
+pub const NAMES: [&str; 2] = ["rules", "bitboard"];
+
+/// A backend at the start position.
+pub fn by_name(name: &str) -> Result<Box<dyn ChessBackend>, String> {
+    match name {
+        "rules" => Ok(Box::new(RulesBackend::new(Position::legal_moves))),
+        "bitboard" => Ok(Box::new(RulesBackend::new(bitboard::legal_moves))),
+        _ => Err(format!("Unknown chess backend {}, expected one of {}", name, NAMES.join(", "))),
+    }
+}
+
+pub struct RulesBackend {
+    pub position: Position,
+    legal_moves: fn(&Position) -> Vec<Move>,
+    /// Square of a pawn promoted by the last move, which `set_promotion` may change.
+    promoted: Option<u8>,
+}
+
+impl RulesBackend {
+    pub fn new(legal_moves: fn(&Position) -> Vec<Move>) -> RulesBackend {
+        RulesBackend { position: Position::start(), legal_moves, promoted: None }
+    }
+
+    fn legal_moves_from(&self, from: u8) -> Vec<Move> {
+        (self.legal_moves)(&self.position).into_iter().filter(|mv| mv.from == from).collect()
+    }
+}
+
+impl ChessBackend for RulesBackend {
+    fn make_move(&mut self, from: &str, to: &str) -> bool {
+        let (from, to) = match (rules::parse_square(from), rules::parse_square(to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false,
+        };
+        let legal_move = self.legal_moves_from(from)
+            .into_iter()
+            .find(|mv| mv.to == to && (mv.promotion == 0 || mv.promotion == rules::QUEEN));
+        match legal_move {
+            Some(mv) => {
+                self.promoted = if mv.promotion != 0 { Some(to) } else { None };
+                self.position.make_move(mv);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_possible_moves(&self, square: &str) -> Vec<String> {
+        let mut targets: Vec<String> = match rules::parse_square(square) {
+            Some(from) => self.legal_moves_from(from).iter().map(|mv| rules::square_name(mv.to)).collect(),
+            None => Vec::new(),
+        };
+        targets.dedup();
+        targets
+    }
+
+    fn set_promotion(&mut self, square: &str, piece: u8) {
+        if let Some(sq) = self.promoted {
+            if Some(sq) == rules::parse_square(square) {
+                self.position.board[sq as usize][1] = piece;
+            }
+        }
+    }
+
+    fn get_fen(&self) -> String {
+        self.position.to_fen()
+    }
+
+    fn get_game_state(&self) -> String {
+        format!("{:?}", self.position.status())
+    }
+}
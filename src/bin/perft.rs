@@ -0,0 +1,28 @@
+//! The `perft` command on its own, which builds without the `gui` feature:
+//!
+//!     cargo run --release --no-default-features --bin perft -- --depth 5
+
+use std::env;
+use std::process;
+
+use ogronman_chess_gui::cli::{self, Command};
+use ogronman_chess_gui::perft;
+
+fn main() {
+    let args: Vec<String> = Some("perft".to_string()).into_iter().chain(env::args().skip(1)).collect();
+    let options = match cli::parse(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(2);
+        }
+    };
+    if options.command == Command::Help {
+        println!("{}", cli::HELP);
+        return;
+    }
+    if let Err(error) = perft::run_command(&options) {
+        println!("{}", error);
+        process::exit(1);
+    }
+}
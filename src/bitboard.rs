@@ -0,0 +1,332 @@
+//! Bitboard legal move generator, written separately from `Position::legal_moves`
+//! so the two can check each other. Moves are generated legal directly: checks
+//! restrict targets to the checking piece and the squares between it and the
+//! king, pinned pieces stay on their pin line and king moves, castling and en
+//! passant test the squares involved for attacks.
+//!
+//! Bit `rank*8+file` is the square of the same index in `Position::board`.
+
+use std::sync::OnceLock;
+
+use crate::rules::{self, Move, Position};
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+const KNIGHT_STEPS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+
+struct Tables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    /// Squares a white and a black pawn on each square attacks.
+    pawn: [[u64; 64]; 2],
+    /// Squares strictly between two squares on a common line, else empty.
+    between: Vec<[u64; 64]>,
+    /// The whole rank, file or diagonal through two squares, else empty.
+    line: Vec<[u64; 64]>,
+}
+
+fn bit(sq: u8) -> u64 {
+    1u64 << sq
+}
+
+/// Iterates over the squares of a bitboard, lowest first.
+fn squares(mut board: u64) -> impl Iterator<Item = u8> {
+    std::iter::from_fn(move || {
+        if board == 0 {
+            return None;
+        }
+        let sq = board.trailing_zeros() as u8;
+        board &= board - 1;
+        Some(sq)
+    })
+}
+
+fn steps(sq: u8, steps: &[(i8, i8)]) -> u64 {
+    steps.iter().filter_map(|(df, dr)| rules::offset(sq, *df, *dr)).fold(0, |board, to| board | bit(to))
+}
+
+/// Squares reached from `sq` in `directions`, each ray stopping at the first occupied square.
+fn slide(sq: u8, occupied: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for (df, dr) in directions.iter() {
+        let mut current = sq;
+        while let Some(to) = rules::offset(current, *df, *dr) {
+            attacks |= bit(to);
+            if occupied & bit(to) != 0 {
+                break;
+            }
+            current = to;
+        }
+    }
+    attacks
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Tables {
+            knight: [0; 64],
+            king: [0; 64],
+            pawn: [[0; 64]; 2],
+            between: vec![[0; 64]; 64],
+            line: vec![[0; 64]; 64],
+        };
+        for sq in 0..64u8 {
+            tables.knight[sq as usize] = steps(sq, &KNIGHT_STEPS);
+            tables.king[sq as usize] = steps(sq, &rules::KING_STEPS);
+            tables.pawn[0][sq as usize] = steps(sq, &[(-1, 1), (1, 1)]);
+            tables.pawn[1][sq as usize] = steps(sq, &[(-1, -1), (1, -1)]);
+            for (df, dr) in ROOK_DIRECTIONS.iter().chain(BISHOP_DIRECTIONS.iter()) {
+                let ray = slide(sq, 0, &[(*df, *dr)]);
+                let backwards = slide(sq, 0, &[(-*df, -*dr)]);
+                let mut between = 0;
+                let mut current = sq;
+                while let Some(to) = rules::offset(current, *df, *dr) {
+                    tables.between[sq as usize][to as usize] = between;
+                    tables.line[sq as usize][to as usize] = ray | backwards | bit(sq);
+                    between |= bit(to);
+                    current = to;
+                }
+            }
+        }
+        tables
+    })
+}
+
+fn rook_attacks(sq: u8, occupied: u64) -> u64 {
+    slide(sq, occupied, &ROOK_DIRECTIONS)
+}
+
+fn bishop_attacks(sq: u8, occupied: u64) -> u64 {
+    slide(sq, occupied, &BISHOP_DIRECTIONS)
+}
+
+/// The position as one bitboard per colour and piece.
+struct Bitboards {
+    /// Indexed by colour (0 white, 1 black) and piece code minus one.
+    pieces: [[u64; 6]; 2],
+    colours: [u64; 2],
+}
+
+impl Bitboards {
+    fn new(position: &Position) -> Bitboards {
+        let mut boards = Bitboards { pieces: [[0; 6]; 2], colours: [0; 2] };
+        for sq in 0..64u8 {
+            let [colour, piece] = position.board[sq as usize];
+            if piece != 0 {
+                let side = if colour == rules::WHITE { 0 } else { 1 };
+                boards.pieces[side][piece as usize - 1] |= bit(sq);
+                boards.colours[side] |= bit(sq);
+            }
+        }
+        boards
+    }
+
+    fn piece(&self, side: usize, piece: u8) -> u64 {
+        self.pieces[side][piece as usize - 1]
+    }
+
+    fn occupied(&self) -> u64 {
+        self.colours[0] | self.colours[1]
+    }
+
+    /// Pieces of `side` attacking `sq` when the board is `occupied`.
+    fn attackers(&self, sq: u8, side: usize, occupied: u64) -> u64 {
+        let tables = tables();
+        let rooks = self.piece(side, rules::ROOK) | self.piece(side, rules::QUEEN);
+        let bishops = self.piece(side, rules::BISHOP) | self.piece(side, rules::QUEEN);
+        (tables.pawn[1 - side][sq as usize] & self.piece(side, rules::PAWN))
+            | (tables.knight[sq as usize] & self.piece(side, rules::KNIGHT))
+            | (tables.king[sq as usize] & self.piece(side, rules::KING))
+            | (rook_attacks(sq, occupied) & rooks)
+            | (bishop_attacks(sq, occupied) & bishops)
+    }
+}
+
+fn push_pawn_moves(moves: &mut Vec<Move>, from: u8, targets: u64) {
+    for to in squares(targets) {
+        if to / 8 == 0 || to / 8 == 7 {
+            for piece in [rules::QUEEN, rules::ROOK, rules::BISHOP, rules::KNIGHT].iter() {
+                moves.push(Move { from, to, promotion: *piece });
+            }
+        } else {
+            moves.push(Move { from, to, promotion: 0 });
+        }
+    }
+}
+
+/// Legal moves of the side to move, in the same form as `Position::legal_moves`.
+pub fn legal_moves(position: &Position) -> Vec<Move> {
+    let tables = tables();
+    let boards = Bitboards::new(position);
+    let us = if position.turn == rules::WHITE { 0 } else { 1 };
+    let them = 1 - us;
+    let own = boards.colours[us];
+    let occupied = boards.occupied();
+    let mut moves = Vec::new();
+
+    let king = match squares(boards.piece(us, rules::KING)).next() {
+        Some(king) => king,
+        None => return moves,
+    };
+    for to in squares(tables.king[king as usize] & !own) {
+        if boards.attackers(to, them, occupied ^ bit(king)) == 0 {
+            moves.push(Move { from: king, to, promotion: 0 });
+        }
+    }
+
+    let checkers = boards.attackers(king, them, occupied);
+    if checkers.count_ones() > 1 {
+        return moves;
+    }
+    let check_mask = match squares(checkers).next() {
+        Some(checker) => checkers | tables.between[king as usize][checker as usize],
+        None => !0,
+    };
+
+    // A piece alone between the king and an enemy slider on the same line is pinned to that line.
+    let mut pinned = 0;
+    let snipers = (rook_attacks(king, 0) & (boards.piece(them, rules::ROOK) | boards.piece(them, rules::QUEEN)))
+        | (bishop_attacks(king, 0) & (boards.piece(them, rules::BISHOP) | boards.piece(them, rules::QUEEN)));
+    for sniper in squares(snipers) {
+        let blockers = tables.between[king as usize][sniper as usize] & occupied;
+        if blockers.count_ones() == 1 {
+            pinned |= blockers & own;
+        }
+    }
+    let allowed = |from: u8| {
+        if pinned & bit(from) != 0 { check_mask & tables.line[king as usize][from as usize] } else { check_mask }
+    };
+
+    let (forward, direction): (i8, i8) = if us == 0 { (8, 1) } else { (-8, -1) };
+    let start_rank = if us == 0 { 1 } else { 6 };
+    for from in squares(boards.piece(us, rules::PAWN)) {
+        let mut targets = tables.pawn[us][from as usize] & boards.colours[them];
+        // A FEN may put a pawn on its last rank, where it has nowhere to go.
+        if let Some(single) = rules::offset(from, 0, direction).filter(|single| occupied & bit(*single) == 0) {
+            targets |= bit(single);
+            let double = (single as i8 + forward) as u8;
+            if from / 8 == start_rank && occupied & bit(double) == 0 {
+                targets |= bit(double);
+            }
+        }
+        push_pawn_moves(&mut moves, from, targets & allowed(from));
+
+        // En passant removes two pieces from a line, so it is tried on the board instead of with masks.
+        if let Some(en_passant) = position.en_passant {
+            if tables.pawn[us][from as usize] & bit(en_passant) != 0 {
+                let captured = (en_passant as i8 - forward) as u8;
+                let after = occupied ^ bit(from) ^ bit(captured) | bit(en_passant);
+                if boards.attackers(king, them, after) & !bit(captured) == 0 {
+                    moves.push(Move { from, to: en_passant, promotion: 0 });
+                }
+            }
+        }
+    }
+
+    for from in squares(boards.piece(us, rules::KNIGHT)) {
+        for to in squares(tables.knight[from as usize] & !own & allowed(from)) {
+            moves.push(Move { from, to, promotion: 0 });
+        }
+    }
+    for piece in [rules::BISHOP, rules::ROOK, rules::QUEEN].iter() {
+        for from in squares(boards.piece(us, *piece)) {
+            let attacks = match *piece {
+                rules::BISHOP => bishop_attacks(from, occupied),
+                rules::ROOK => rook_attacks(from, occupied),
+                _ => bishop_attacks(from, occupied) | rook_attacks(from, occupied),
+            };
+            for to in squares(attacks & !own & allowed(from)) {
+                moves.push(Move { from, to, promotion: 0 });
+            }
+        }
+    }
+
+    if checkers == 0 {
+        castling_moves(position, &boards, king, &mut moves);
+    }
+    moves
+}
+
+/// Castling moves of the side to move, which is not in check.
+fn castling_moves(position: &Position, boards: &Bitboards, king: u8, moves: &mut Vec<Move>) {
+    let us = if position.turn == rules::WHITE { 0 } else { 1 };
+    let back_rank = if us == 0 { 0 } else { 7 };
+    if king / 8 != back_rank {
+        return;
+    }
+    let sides = if us == 0 {
+        [rules::WHITE_KING_SIDE, rules::WHITE_QUEEN_SIDE]
+    } else {
+        [rules::BLACK_KING_SIDE, rules::BLACK_QUEEN_SIDE]
+    };
+    let occupied = boards.occupied();
+
+    for side in sides.iter() {
+        let rook = match position.castling[*side] {
+            Some(file) => back_rank * 8 + file,
+            None => continue,
+        };
+        if boards.piece(us, rules::ROOK) & bit(rook) == 0 {
+            continue;
+        }
+        let king_side = *side == rules::WHITE_KING_SIDE || *side == rules::BLACK_KING_SIDE;
+        let king_to = back_rank * 8 + if king_side { 6 } else { 2 };
+        let rook_to = back_rank * 8 + if king_side { 5 } else { 3 };
+
+        let span = |a: u8, b: u8| (a.min(b)..=a.max(b)).fold(0u64, |board, sq| board | bit(sq));
+        let others = occupied & !bit(king) & !bit(rook);
+        if (span(king, king_to) | span(rook, rook_to)) & others != 0 {
+            continue;
+        }
+        let path = span(king, king_to) & !bit(king_to);
+        if squares(path).any(|sq| boards.attackers(sq, 1 - us, others) != 0) {
+            continue;
+        }
+        if boards.attackers(king_to, 1 - us, others | bit(king_to) | bit(rook_to)) != 0 {
+            continue;
+        }
+        moves.push(Move { from: king, to: if position.chess960 { rook } else { king_to }, promotion: 0 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perft::{self, SUITE};
+
+    /// Legal moves sorted, so two generators' lists compare equal.
+    fn sorted(mut moves: Vec<Move>) -> Vec<Move> {
+        moves.sort_by_key(|mv| (mv.from, mv.to, mv.promotion));
+        moves
+    }
+
+    #[test]
+    fn perft_suite_to_depth_3() {
+        for (name, fen, counts) in SUITE.iter() {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(perft::perft(&position, 3, legal_moves), counts[2], "{}", name);
+        }
+    }
+
+    #[test]
+    fn agrees_with_rules_on_the_suite() {
+        for (name, fen, _) in SUITE.iter() {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(sorted(legal_moves(&position)), sorted(position.legal_moves()), "{}", name);
+            for mv in position.legal_moves() {
+                let mut next = position.clone();
+                next.make_move(mv);
+                assert_eq!(sorted(legal_moves(&next)), sorted(next.legal_moves()), "{} after {}", name, mv.to_uci());
+            }
+        }
+    }
+
+    #[test]
+    fn pawns_on_their_last_rank_cannot_push() {
+        let position = Position::from_fen("P3k3/8/8/8/8/8/8/4K2p w - - 0 1").unwrap();
+        assert!(legal_moves(&position).iter().all(|mv| mv.from != 56));
+        let position = Position::from_fen("P3k3/8/8/8/8/8/8/4K2p b - - 0 1").unwrap();
+        assert!(legal_moves(&position).iter().all(|mv| mv.from != 7));
+    }
+}
@@ -0,0 +1,243 @@
+//! Command line parsing.
+
+use crate::clock::TimeControl;
+use crate::rules::Position;
+
+pub const HELP: &str = "\
+Schnack, a chess GUI
+
+Usage:
+    ogronman-chess-gui [play] [options]
+    ogronman-chess-gui analyze <pgn> [options]
+    ogronman-chess-gui setup [options]
+    ogronman-chess-gui replay <pgn> [options]
+    ogronman-chess-gui report <pgn> [--depth <n>] [--pgn <file>]
+    ogronman-chess-gui match <engine> <engine> [options]
+    ogronman-chess-gui perft [--depth <n>] [--fen <fen>] [--compare]
+
+Commands:
+    play                 Play a game (the default)
+    analyze <pgn>        Load the first game of a PGN file and analyse it
+    setup                Open the board editor
+    replay <pgn>         Browse and replay the games of a PGN file
+    report <pgn>         Review the first game of a PGN file with the engine and write it annotated
+    match <a> <b>        Play a match between two UCI engines
+    perft                Count move paths of the standard perft positions, or of --fen per move
+
+Options:
+    --white <player>     human or engine:<path>, human by default
+    --black <player>     human or engine:<path>, human by default
+    --fen <fen>          Start from this position instead of the initial one
+    --time <control>     Clock in minutes plus increment in seconds, e.g. 5+3
+    --flip               Draw the board from the other side
+    --no-flip            Draw the board from White's side, whatever the settings file says
+    --theme <name>       Colour theme
+    --promotion <piece>  Promotion piece: q, r, b or n
+    --window <WxH>       Window size, e.g. 1020x720
+    --animation <ms>     Length of move animations, 0 to turn them off
+    -h, --help           Print this help
+
+Match options:
+    --games <n>          Number of games, 10 by default
+    --openings <file>    Start positions, one FEN or EPD per line, each played with both colours
+    --pgn <file>         File the games are appended to, match.pgn by default
+    --headless           Play without a window and print the score
+
+Report options:
+    --depth <n>          Search depth per position, 12 by default
+    --pgn <file>         File the annotated game is written to, report.pgn by default
+
+Perft options:
+    --depth <n>          Plies to count, 4 by default
+    --compare            Also check the bitboard generator against the rules module
+
+--flip, --no-flip, --theme, --promotion, --window and --animation override the settings file for this launch.";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Player {
+    Human,
+    /// UCI engine at the given path.
+    Engine(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Play,
+    /// Analyse the game in the given PGN file.
+    Analyze(String),
+    Setup,
+    /// Replay the games in the given PGN file.
+    Replay(String),
+    /// Review the game in the given PGN file without a window.
+    Report(String),
+    /// Match between the engines at the two paths.
+    Match(String, String),
+    Perft,
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub white: Player,
+    pub black: Player,
+    pub fen: Option<String>,
+    pub time: Option<TimeControl>,
+    /// Orientation from `--flip` or `--no-flip`, overriding the settings file.
+    pub flip: Option<bool>,
+    pub games: usize,
+    pub openings: Option<String>,
+    pub pgn: Option<String>,
+    pub headless: bool,
+    pub depth: Option<u32>,
+    pub compare: bool,
+    /// Settings file overrides as key and value, see `Settings::set`.
+    pub settings: Vec<(String, String)>,
+}
+
+impl Player {
+    fn parse(text: &str) -> Result<Player, String> {
+        if text == "human" {
+            return Ok(Player::Human);
+        }
+        match text.strip_prefix("engine:") {
+            Some(path) if !path.is_empty() => Ok(Player::Engine(path.to_string())),
+            _ => Err(format!("Player must be human or engine:<path>, not {}", text)),
+        }
+    }
+}
+
+/// A `--fen` value, checked before anything is started with it.
+fn parse_fen(text: &str) -> Result<String, String> {
+    Position::from_fen(text)
+        .and_then(|position| position.validate())
+        .map_err(|error| format!("Bad FEN {}: {}", text, error))?;
+    Ok(text.to_string())
+}
+
+/// Parses the arguments after the program name.
+pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Play,
+        white: Player::Human,
+        black: Player::Human,
+        fen: None,
+        time: None,
+        flip: None,
+        games: 10,
+        openings: None,
+        pgn: None,
+        headless: false,
+        depth: None,
+        compare: false,
+        settings: Vec::new(),
+    };
+
+    let mut args = args.iter().peekable();
+    match args.peek().map(|arg| arg.as_str()) {
+        Some("play") => {
+            args.next();
+        }
+        Some("setup") => {
+            args.next();
+            options.command = Command::Setup;
+        }
+        Some("analyze") | Some("analyse") => {
+            args.next();
+            let pgn = args.next().filter(|arg| !arg.starts_with("--")).ok_or("analyze needs a PGN file".to_string())?;
+            options.command = Command::Analyze(pgn.clone());
+        }
+        Some("perft") => {
+            args.next();
+            options.command = Command::Perft;
+        }
+        Some("replay") => {
+            args.next();
+            let pgn = args.next().filter(|arg| !arg.starts_with("--")).ok_or("replay needs a PGN file".to_string())?;
+            options.command = Command::Replay(pgn.clone());
+        }
+        Some("report") => {
+            args.next();
+            let pgn = args.next().filter(|arg| !arg.starts_with("--")).ok_or("report needs a PGN file".to_string())?;
+            options.command = Command::Report(pgn.clone());
+        }
+        Some("match") => {
+            args.next();
+            let mut engine = || args.next().filter(|arg| !arg.starts_with("--")).cloned().ok_or("match needs two engines".to_string());
+            options.command = Command::Match(engine()?, engine()?);
+        }
+        _ => {}
+    }
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => options.command = Command::Help,
+            "--white" => options.white = Player::parse(&value()?)?,
+            "--black" => options.black = Player::parse(&value()?)?,
+            "--fen" => options.fen = Some(parse_fen(&value()?)?),
+            "--time" => options.time = Some(TimeControl::parse(&value()?)?),
+            "--flip" => options.flip = Some(true),
+            "--no-flip" => options.flip = Some(false),
+            "--games" => options.games = value()?.parse().map_err(|_| "--games needs a number".to_string())?,
+            "--openings" => options.openings = Some(value()?),
+            "--pgn" => options.pgn = Some(value()?),
+            "--headless" => options.headless = true,
+            "--depth" => options.depth = Some(value()?.parse().map_err(|_| "--depth needs a number".to_string())?),
+            "--compare" => options.compare = true,
+            "--theme" | "--promotion" | "--window" | "--animation" => options.settings.push((arg[2..].to_string(), value()?)),
+            _ => return Err(format!("Unknown argument {}, see --help", arg)),
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_a_match() {
+        let options = parse(&args("match ./a ./b --games 4 --headless")).unwrap();
+        assert_eq!(options.command, Command::Match("./a".to_string(), "./b".to_string()));
+        assert_eq!(options.games, 4);
+        assert!(options.headless);
+        assert_eq!(parse(&args("match ./a --games 4")).unwrap_err(), "match needs two engines");
+        assert_eq!(parse(&args("match ./a ./b --games four")).unwrap_err(), "--games needs a number");
+    }
+
+    #[test]
+    fn commands_need_their_file() {
+        assert_eq!(parse(&args("analyze")).unwrap_err(), "analyze needs a PGN file");
+        assert_eq!(parse(&args("analyze --flip")).unwrap_err(), "analyze needs a PGN file");
+        assert_eq!(parse(&args("analyze game.pgn")).unwrap().command, Command::Analyze("game.pgn".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_and_incomplete_arguments() {
+        assert_eq!(parse(&args("--fast")).unwrap_err(), "Unknown argument --fast, see --help");
+        assert_eq!(parse(&args("--white")).unwrap_err(), "--white needs a value");
+        assert!(parse(&args("--white robot")).is_err());
+        assert_eq!(parse(&args("--theme")).unwrap_err(), "--theme needs a value");
+    }
+
+    #[test]
+    fn checks_fen_and_time() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let mut line = args("--time 5+3 --fen");
+        line.push(fen.to_string());
+        let options = parse(&line).unwrap();
+        assert_eq!(options.fen.as_deref(), Some(fen));
+        assert_eq!(options.time, Some(TimeControl::parse("5+3").unwrap()));
+
+        assert!(parse(&args("--fen 8/8/8 w - - 0 1")).is_err());
+        assert!(parse(&args("--fen 8/8/8/8/8/8/8/8 w - - 0 1")).unwrap_err().starts_with("Bad FEN"));
+        assert_eq!(parse(&args("--time")).unwrap_err(), "--time needs a value");
+        assert_eq!(parse(&args("--time fast")).unwrap_err(), "Bad time control fast, expected e.g. 5+3");
+        assert!(parse(&args("--time 0+2")).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Chess clock with a base time and an increment per move.
+
+use std::time::{Duration, Instant};
+
+use crate::rules;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+pub struct Clock {
+    pub control: TimeControl,
+    /// Time left for White and Black, not counting the running period.
+    remaining: [Duration; 2],
+    /// Side whose clock runs, and since when.
+    running: Option<(u8, Instant)>,
+}
+
+fn index(colour: u8) -> usize {
+    if colour == rules::WHITE { 0 } else { 1 }
+}
+
+/// Longest base time and increment accepted, a day and an hour.
+const MAX_MINUTES: f64 = 24.0 * 60.0;
+const MAX_INCREMENT: f64 = 60.0 * 60.0;
+
+impl TimeControl {
+    /// Parses "minutes+increment", e.g. "5+3", "0.5+1" or "10".
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let mut parts = text.splitn(2, '+');
+        let minutes: f64 = parts.next().unwrap_or("").trim().parse()
+            .map_err(|_| format!("Bad time control {}, expected e.g. 5+3", text))?;
+        let increment: f64 = match parts.next() {
+            Some(seconds) => seconds.trim().parse().map_err(|_| format!("Bad increment in time control {}", text))?,
+            None => 0.0,
+        };
+        // "inf" and "nan" parse as numbers too.
+        if !minutes.is_finite() || !increment.is_finite() || minutes <= 0.0 || increment < 0.0 {
+            return Err(format!("Time control {} must have a positive base time", text));
+        }
+        if minutes > MAX_MINUTES || increment > MAX_INCREMENT {
+            return Err(format!("Time control {} is longer than {} minutes plus {} seconds", text, MAX_MINUTES, MAX_INCREMENT));
+        }
+        Ok(TimeControl {
+            base: Duration::from_secs_f64(minutes * 60.0),
+            increment: Duration::from_secs_f64(increment),
+        })
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            remaining: [control.base; 2],
+            running: None,
+        }
+    }
+
+    /// Time left for `colour`, including the running period.
+    pub fn remaining(&self, colour: u8) -> Duration {
+        let left = self.remaining[index(colour)];
+        match self.running {
+            Some((running, since)) if running == colour => left.checked_sub(since.elapsed()).unwrap_or(Duration::from_secs(0)),
+            _ => left,
+        }
+    }
+
+    /// Stops the running clock, adding the increment, and starts `to_move`'s clock.
+    pub fn switch(&mut self, to_move: u8) {
+        if let Some((running, _)) = self.running {
+            if running == to_move {
+                return;
+            }
+            self.remaining[index(running)] = self.remaining(running) + self.control.increment;
+        }
+        self.running = Some((to_move, Instant::now()));
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn stop(&mut self) {
+        if let Some((running, _)) = self.running {
+            self.remaining[index(running)] = self.remaining(running);
+            self.running = None;
+        }
+    }
+
+    /// Side whose time has run out, if any.
+    pub fn flagged(&self) -> Option<u8> {
+        match self.running {
+            Some((running, _)) if self.remaining(running) == Duration::from_secs(0) => Some(running),
+            _ => None,
+        }
+    }
+
+    /// "m:ss", with tenths of a second under twenty seconds.
+    pub fn format(time: Duration) -> String {
+        if time < Duration::from_secs(20) {
+            return format!("0:{:04.1}", time.as_secs_f64());
+        }
+        let seconds = time.as_secs();
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+
+    /// Side panel line with both clocks.
+    pub fn status_line(&self) -> String {
+        format!("White {}  Black {}", Clock::format(self.remaining(rules::WHITE)), Clock::format(self.remaining(rules::BLACK)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_time_controls() {
+        assert_eq!(TimeControl::parse("5+3"), Ok(TimeControl { base: Duration::from_secs(300), increment: Duration::from_secs(3) }));
+        assert_eq!(TimeControl::parse("0.5").map(|control| control.base), Ok(Duration::from_secs(30)));
+        for text in ["inf", "nan", "1e300", "5+inf", "5+nan", "0", "-1+2", "5+-1", "x"].iter() {
+            assert!(TimeControl::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn stopped_clock_keeps_its_time() {
+        let mut clock = Clock::new(TimeControl::parse("1+2").unwrap());
+        clock.switch(rules::WHITE);
+        clock.stop();
+        assert!(!clock.is_running());
+        let left = clock.remaining(rules::WHITE);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(clock.remaining(rules::WHITE), left);
+        // Restarting the same side adds no increment.
+        clock.switch(rules::WHITE);
+        assert!(clock.is_running() && clock.remaining(rules::WHITE) <= left);
+    }
+}
@@ -0,0 +1,66 @@
+//! Opening names from the bundled ECO table in `resources/eco.tsv`.
+//!
+//! Each line is `code`, `name` and the moves of the opening in SAN. Openings
+//! are matched by the position they reach, so transpositions are recognised.
+
+use std::collections::HashMap;
+
+use crate::pgn::{self, Token};
+use crate::rules::Position;
+
+const ECO_TABLE: &str = include_str!("../resources/eco.tsv");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+}
+
+pub struct EcoTable {
+    /// Openings by the Zobrist hash of their final position.
+    positions: HashMap<u64, Opening>,
+}
+
+impl Opening {
+    /// E.g. "C65 Ruy Lopez, Berlin Defence".
+    pub fn to_text(&self) -> String {
+        format!("{} {}", self.eco, self.name)
+    }
+}
+
+impl EcoTable {
+    pub fn load() -> EcoTable {
+        let mut positions = HashMap::new();
+        for line in ECO_TABLE.lines().skip(1) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 3 {
+                continue;
+            }
+            let mut position = Position::start();
+            let mut legal = true;
+            for token in pgn::tokenize(fields[2]) {
+                if let Token::Move(san) = token {
+                    match position.parse_san(&san) {
+                        Some(mv) => position.make_move(mv),
+                        None => {
+                            println!("Bad ECO line for {}: {}", fields[0], san);
+                            legal = false;
+                            break;
+                        }
+                    }
+                }
+            }
+            if legal {
+                positions.insert(position.zobrist(), Opening { eco: fields[0].to_string(), name: fields[1].to_string() });
+            }
+        }
+        EcoTable { positions }
+    }
+
+    /// The opening of the latest position in `fens` that the table knows.
+    pub fn classify(&self, fens: &[String]) -> Option<&Opening> {
+        fens.iter().rev()
+            .filter_map(|fen| Position::from_fen(fen).ok())
+            .find_map(|position| self.positions.get(&position.zobrist()))
+    }
+}
@@ -0,0 +1,164 @@
+//! Opening explorer built from a local folder of PGN files.
+//!
+//! Every position of the first `MAX_PLY` plies of each game is indexed by its
+//! Zobrist hash, together with the move played from it and the game result.
+//! The index is saved next to the games so later launches skip the import.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::pgn::{self, PgnGame};
+use crate::rules::Position;
+
+/// Only the opening phase of each game is indexed.
+const MAX_PLY: usize = 40;
+/// Index file written into the games folder.
+pub const INDEX_FILE: &str = "opening_index.txt";
+
+/// Results of the games in which a move was played.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveStats {
+    pub white: u32,
+    pub draws: u32,
+    pub black: u32,
+}
+
+pub struct Explorer {
+    positions: HashMap<u64, Vec<(String, MoveStats)>>,
+    pub games: u32,
+}
+
+impl MoveStats {
+    pub fn total(&self) -> u32 {
+        self.white + self.draws + self.black
+    }
+
+    /// White wins, draws and black wins in whole percent.
+    pub fn percentages(&self) -> (u32, u32, u32) {
+        let total = self.total().max(1);
+        (self.white * 100 / total, self.draws * 100 / total, self.black * 100 / total)
+    }
+}
+
+impl Default for Explorer {
+    fn default() -> Explorer {
+        Explorer::new()
+    }
+}
+
+impl Explorer {
+    pub fn new() -> Explorer {
+        Explorer {
+            positions: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    /// Loads the saved index in `dir`, or imports every `.pgn` file in it and saves the index.
+    pub fn open(dir: &Path) -> Result<Explorer, String> {
+        let index_path = dir.join(INDEX_FILE);
+        if index_path.exists() {
+            return Explorer::load(&index_path);
+        }
+
+        let mut explorer = Explorer::new();
+        let entries = fs::read_dir(dir).map_err(|error| format!("Failed to read {}: {}", dir.display(), error))?;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("pgn") {
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    for game in pgn::parse_games(&text).iter() {
+                        explorer.add_game(game);
+                    }
+                }
+                Err(error) => println!("Skipping {}: {}", path.display(), error),
+            }
+        }
+        println!("Indexed {} games from {}", explorer.games, dir.display());
+        explorer.save(&index_path)?;
+        Ok(explorer)
+    }
+
+    pub fn add_game(&mut self, game: &PgnGame) {
+        let (white, draws, black) = match game.header("Result") {
+            Some("1-0") => (1, 0, 0),
+            Some("0-1") => (0, 0, 1),
+            Some("1/2-1/2") => (0, 1, 0),
+            _ => return,
+        };
+        let mut position = match Position::from_fen(&game.start_fen()) {
+            Ok(position) => position,
+            Err(_) => return,
+        };
+
+        for san in game.main_line().iter().take(MAX_PLY) {
+            let mv = match position.parse_san(san) {
+                Some(mv) => mv,
+                None => break,
+            };
+            let san = position.move_to_san(&mv);
+            let moves = self.positions.entry(position.zobrist()).or_default();
+            let index = match moves.iter().position(|(played, _)| *played == san) {
+                Some(index) => index,
+                None => {
+                    moves.push((san, MoveStats::default()));
+                    moves.len() - 1
+                }
+            };
+            moves[index].1.white += white;
+            moves[index].1.draws += draws;
+            moves[index].1.black += black;
+            position.make_move(mv);
+        }
+        self.games += 1;
+    }
+
+    /// Moves played from `position`, most popular first.
+    pub fn moves(&self, position: &Position) -> Vec<(String, MoveStats)> {
+        let mut moves = self.positions.get(&position.zobrist()).cloned().unwrap_or(Vec::new());
+        moves.sort_by_key(|(_, stats)| Reverse(stats.total()));
+        moves
+    }
+
+    /// Writes one line per position and move: `hash san white draws black`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = format!("games {}\n", self.games);
+        for (hash, moves) in self.positions.iter() {
+            for (san, stats) in moves.iter() {
+                text.push_str(&format!("{:016x} {} {} {} {}\n", hash, san, stats.white, stats.draws, stats.black));
+            }
+        }
+        fs::write(path, text).map_err(|error| format!("Failed to write {}: {}", path.display(), error))
+    }
+
+    pub fn load(path: &Path) -> Result<Explorer, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        let mut explorer = Explorer::new();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() == 2 && fields[0] == "games" {
+                explorer.games = fields[1].parse().unwrap_or(0);
+                continue;
+            }
+            if fields.len() != 5 {
+                continue;
+            }
+            let hash = match u64::from_str_radix(fields[0], 16) {
+                Ok(hash) => hash,
+                Err(_) => continue,
+            };
+            let stats = MoveStats {
+                white: fields[2].parse().unwrap_or(0),
+                draws: fields[3].parse().unwrap_or(0),
+                black: fields[4].parse().unwrap_or(0),
+            };
+            explorer.positions.entry(hash).or_default().push((fields[1].to_string(), stats));
+        }
+        Ok(explorer)
+    }
+}
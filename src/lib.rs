@@ -0,0 +1,32 @@
+//! Chess model behind the Schnack GUI: rules, FEN and PGN, openings, puzzles,
+//! clocks and settings, and with the `uci` feature UCI engines and engine
+//! matches. None of it depends on ggez, so it builds and tests without a
+//! window system, e.g. with `cargo test --lib --no-default-features`.
+
+pub mod animation;
+pub mod annotations;
+pub mod backend;
+pub mod bitboard;
+pub mod cli;
+pub mod clock;
+pub mod eco;
+pub mod explorer;
+pub mod move_entry;
+pub mod perft;
+pub mod pgn;
+pub mod puzzle;
+pub mod replay;
+#[cfg(feature = "uci")]
+pub mod report;
+pub mod rules;
+pub mod search;
+pub mod settings;
+pub mod setup;
+pub mod theme;
+#[cfg(feature = "uci")]
+pub mod tournament;
+pub mod tree;
+#[cfg(feature = "uci")]
+pub mod uci;
+pub mod validate;
+pub mod variant;
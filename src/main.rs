@@ -1,8 +1,34 @@
-use ggez::{conf, event, graphics, ContextBuilder, Context, GameError, GameResult};
+use ggez::{conf, event, graphics, timer, ContextBuilder, Context, GameError, GameResult};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::input::keyboard;
 use std::{path, env, collections::HashMap};
-use alholmbe_chess::{ Game, GameState, Colour, Piece };
 use std::{thread, time};
 
+mod sound;
+
+use ogronman_chess_gui::{animation, annotations, backend, cli, clock, eco, explorer, move_entry, perft, pgn, puzzle, replay, report, rules, search, settings, setup, theme, tournament, tree, uci, validate, variant};
+use animation::Animation;
+use annotations::Annotations;
+use backend::ChessBackend;
+use cli::{Command, Options, Player};
+use clock::{Clock, TimeControl};
+use eco::{EcoTable, Opening};
+use explorer::{Explorer, MoveStats};
+use pgn::GameRecord;
+use move_entry::MoveEntry;
+use puzzle::{PuzzleState, Trainer};
+use replay::Replay;
+use report::{Report, Review};
+use rules::Position;
+use settings::Settings;
+use setup::Setup;
+use sound::{Sound, Sounds};
+use theme::Theme;
+use tournament::Tournament;
+use tree::MoveTree;
+use uci::Engine;
+use variant::{Variant, VariantGame};
+
 
 /// A chess board is 8x8 tiles.
 const GRID_SIZE: i16 = 8;
@@ -14,70 +40,345 @@ const SCREEN_SIZE: (f32, f32) = (
     GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32,
     GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32,
 );
+/// Board plus information panel. Everything is laid out in these coordinates and scaled to the window.
+const WINDOW_SIZE: (f32, f32) = (SCREEN_SIZE.0 + 300.0, SCREEN_SIZE.1);
 
-// GUI Color representations
-const BLACK: graphics::Color = graphics::Color::new(228.0/255.0, 196.0/255.0, 108.0/255.0, 1.0);
-const DARKYELLOW: graphics::Color = graphics::Color::new(240.0/255.0, 210.0/255.0, 90.0/255.0, 1.0);
-const LIGHTYELLOW: graphics::Color = graphics::Color::new(255.0/255.0, 210.0/255.0, 90.0/255.0, 1.0);
-const WHITE: graphics::Color = graphics::Color::new(188.0/255.0, 140.0/255.0, 76.0/255.0, 1.0);
-
+// GUI Color representations, the board colours come from the theme.
 const REALBLACK: graphics::Color = graphics::Color::new(0.0/255.0, 0.0/255.0, 0.0/255.0, 1.0);
 
+/// Top left corner of the setup palette in the information panel.
+const PALETTE_ORIGIN: (f32, f32) = (SCREEN_SIZE.0 + 25.0, 340.0);
+/// Palette tiles match the native sprite size.
+const PALETTE_TILE: f32 = 45.0;
+
+/// Engine used for analysis when `UCI_ENGINE` is not set.
+const DEFAULT_ENGINE: &str = "stockfish";
+/// Width of the evaluation bar between the board and the information panel.
+const EVAL_BAR_WIDTH: f32 = 15.0;
+
+/// File the game is saved to with `P`.
+const PGN_EXPORT_PATH: &str = "game.pgn";
+/// File opened by the replay viewer when `REPLAY_FILE` is not set.
+const DEFAULT_REPLAY_FILE: &str = PGN_EXPORT_PATH;
+/// File the annotated game is saved to when a report is done, and by the `report` command without `--pgn`.
+const REPORT_PGN_PATH: &str = "report.pgn";
+/// Height of the evaluation graph in the report panel.
+const REPORT_GRAPH_HEIGHT: f32 = 80.0;
+/// Colour of the engine's best move arrow.
+const BEST_MOVE_COLOUR: graphics::Color = graphics::Color::new(0.2, 0.4, 0.9, 0.6);
+/// Squares of a queued premove.
+const PREMOVE_COLOUR: graphics::Color = graphics::Color::new(0.8, 0.2, 0.2, 0.45);
+/// Opacity of the legal move markers, drawn in the theme's highlight colours.
+const MOVE_MARKER_ALPHA: f32 = 0.8;
+/// Piece and target square of a hint.
+const HINT_COLOUR: graphics::Color = graphics::Color::new(0.1, 0.7, 0.3, 0.45);
+/// Thinking time of the UCI engine asked for a hint.
+const HINT_MOVE_TIME: time::Duration = time::Duration::from_millis(1000);
+
+/// Puzzle set used when `PUZZLE_FILE` is not set.
+const DEFAULT_PUZZLE_FILE: &str = "puzzles.csv";
+/// Pause before the trainer plays the opponent's reply.
+const PUZZLE_REPLY_DELAY: time::Duration = time::Duration::from_millis(600);
+/// Top of the mode status lines between the game state and the promotion selector.
+const STATUS_LINES_Y: f32 = 222.0;
+
+/// Folder of PGN files used by the explorer when `EXPLORER_DIR` is not set.
+const DEFAULT_EXPLORER_DIR: &str = "games";
+/// Top of the first explorer move row, and the number of rows shown.
+const EXPLORER_ROWS_Y: f32 = 335.0;
+const EXPLORER_ROWS: usize = 10;
+const PANEL_LINE_HEIGHT: f32 = 22.0;
+
+/// Thinking time per move for engine players when there is no clock.
+const ENGINE_MOVE_TIME: time::Duration = time::Duration::from_millis(1000);
+
+/// Time control of engine matches without `--time`.
+const DEFAULT_MATCH_TIME: TimeControl = TimeControl { base: time::Duration::from_secs(60), increment: time::Duration::from_secs(1) };
+/// File match games are appended to without `--pgn`.
+const DEFAULT_MATCH_PGN: &str = "match.pgn";
+
+/// Folder of extra `.toml` themes used when `THEME_DIR` is not set.
+const DEFAULT_THEME_DIR: &str = "themes";
+/// Rules backend for standard games when `CHESS_BACKEND` is not set.
+const DEFAULT_BACKEND: &str = "rules";
+
 
 /// GUI logic and event implementation structure. 
 struct AppState {
     sprites: HashMap<(u8, u8), graphics::Image>,
     board: Vec<Vec<u8>>,
-    game: Game,
+    game: Box<dyn ChessBackend>,
+    /// Folder for move generation bug reports. The backend is only checked against the
+    /// bitboard generator when `VALIDATE_MOVES` names one.
+    validate_dir: Option<path::PathBuf>,
+    /// Set while `rebuild_to` replays moves, which are not checked one by one.
+    replaying: bool,
     turn: u8,
     promote_piece: char,
     current_turn: String,
-    current_piece: Vec<u8>
+    current_piece: Vec<u8>,
+    /// Set when playing a variant or from an edited position, which `game` cannot do.
+    variant_game: Option<VariantGame>,
+    /// Variant of the next game started.
+    variant: Variant,
+    /// Set while the board editor is open.
+    setup: Option<Setup>,
+    /// Running analysis engine, if analysis mode is on.
+    engine: Option<Engine>,
+    engine_path: String,
+    /// Depth, score and best line of the running analysis, ready for the side panel.
+    analysis_lines: Vec<String>,
+    /// Moves played so far, for PGN export.
+    record: GameRecord,
+    /// Every line tried from the start position, with `record` the path to its current node.
+    tree: MoveTree,
+    /// Set while a comment on the current move is being typed.
+    comment_entry: Option<String>,
+    /// Set while the move tree is shown in place of the promotion selector.
+    show_moves: bool,
+    /// Arrows and circles drawn by the user in this game, keyed by `rules::position_key`.
+    annotations: HashMap<String, Annotations>,
+    /// Square where the current right-click drag started.
+    arrow_start: Option<u8>,
+    show_best_move: bool,
+    /// Move given as a hint, and whether its target square is shown as well as the piece.
+    hint: Option<(rules::Move, bool)>,
+    /// Engine asked for hints when `UCI_ENGINE` is set, kept between hints.
+    hint_engine: Option<Engine>,
+    /// Hints taken by White and Black this game, saved in the PGN.
+    hints: [u32; 2],
+    /// Set while the puzzle trainer is on.
+    trainer: Option<Trainer>,
+    /// Number of the Chess960 start position being played, if any.
+    chess960: Option<u16>,
+    /// Opponent reply the trainer plays once the delay has passed.
+    puzzle_reply: Option<(rules::Move, time::Instant)>,
+    /// Set while the opening explorer is shown in place of the promotion selector.
+    explorer: Option<Explorer>,
+    /// Explorer moves for the current position.
+    explorer_moves: Vec<(String, MoveStats)>,
+    eco: EcoTable,
+    /// Opening of the current game, if the ECO table knows it.
+    opening: Option<Opening>,
+    themes: Vec<Theme>,
+    /// Index of the theme in use.
+    theme: usize,
+    /// Board drawn with the squares rotated half a turn.
+    flipped: bool,
+    /// Current window size, for mapping mouse positions to the layout.
+    window_size: (f32, f32),
+    /// Settings as saved in the settings file, without command line overrides.
+    settings: Settings,
+    /// White and Black.
+    players: [Player; 2],
+    /// Engines playing for White and Black.
+    player_engines: [Option<Engine>; 2],
+    time_control: Option<TimeControl>,
+    clock: Option<Clock>,
+    /// Side that lost other than on the board, and how, e.g. on time.
+    forfeit: Option<(u8, &'static str)>,
+    /// Move queued while the engine opponent thinks, as from and to squares.
+    premove: Option<(u8, u8)>,
+    sounds: Sounds,
+    /// Whether White's and Black's low time warning has sounded this game.
+    low_time_warned: [bool; 2],
+    /// PGN replay viewer, shown on the board in place of the game while open.
+    replay: Option<Replay>,
+    /// Whether the game clock was running when the replay viewer opened, to restart it on closing.
+    clock_paused: bool,
+    /// Pieces sliding after the last move.
+    animation: Option<Animation>,
+    animation_duration: time::Duration,
+    /// Set while a move is being typed.
+    move_entry: Option<MoveEntry>,
+    /// Completions of the typed move, for the side panel.
+    move_suggestions: Vec<String>,
+    /// Post-game review being run, with the move tree it was started on.
+    review: Option<(Review, MoveTree)>,
+    /// Finished post-game report, shown in place of the promotion selector.
+    report: Option<Report>,
+    /// Engine match being played, if any.
+    tournament: Option<Tournament>,
+    /// Whether the match's second engine has White in the current game.
+    match_swapped: bool,
 
     // Save piece positions, which tiles has been clicked, current colour, etc...
 }
 
 impl AppState {
     /// Initialise new application, i.e. initialise new game and load resources.
-    fn new(ctx: &mut Context) -> GameResult<AppState> {
+    /// `settings` are the saved settings and `launch` the ones with command line overrides applied.
+    fn new(ctx: &mut Context, settings: Settings, launch: &Settings) -> GameResult<AppState> {
         
+        let themes = Theme::load_all(path::Path::new(&env::var("THEME_DIR").unwrap_or(DEFAULT_THEME_DIR.to_string())));
+        let theme = themes.iter().position(|theme| theme.name.eq_ignore_ascii_case(&launch.theme)).unwrap_or_else(|| {
+            println!("Unknown theme {}, using {}", launch.theme, themes[0].name);
+            0
+        });
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, WINDOW_SIZE.0, WINDOW_SIZE.1))?;
         let state = AppState {
-            sprites: AppState::load_sprites(ctx),
+            sprites: AppState::load_sprites(ctx, &themes[theme].pieces),
             board: Vec::with_capacity(64),
-            game: Game::new(),
+            game: AppState::new_backend(),
             turn: 8,
-            promote_piece: 'q',
+            promote_piece: if launch.promotion == 'n' { 'k' } else { launch.promotion },
             current_turn: "White".to_string(),
             current_piece: vec![0,0,64],
+            variant_game: None,
+            variant: Variant::Standard,
+            setup: None,
+            engine: None,
+            engine_path: env::var("UCI_ENGINE").unwrap_or(DEFAULT_ENGINE.to_string()),
+            validate_dir: env::var_os("VALIDATE_MOVES").map(path::PathBuf::from),
+            replaying: false,
+            analysis_lines: Vec::new(),
+            record: GameRecord::new(rules::START_FEN.to_string()),
+            tree: MoveTree::new(rules::START_FEN.to_string()),
+            comment_entry: None,
+            show_moves: false,
+            annotations: HashMap::new(),
+            arrow_start: None,
+            show_best_move: false,
+            hint: None,
+            hint_engine: None,
+            hints: [0, 0],
+            trainer: None,
+            chess960: None,
+            puzzle_reply: None,
+            explorer: None,
+            explorer_moves: Vec::new(),
+            eco: EcoTable::load(),
+            opening: None,
+            themes,
+            theme,
+            flipped: launch.flipped,
+            window_size: launch.window,
+            settings,
+            players: [Player::Human, Player::Human],
+            player_engines: [None, None],
+            time_control: None,
+            clock: None,
+            forfeit: None,
+            premove: None,
+            sounds: Sounds::new(ctx, launch.muted),
+            low_time_warned: [false; 2],
+            replay: None,
+            clock_paused: false,
+            animation: None,
+            animation_duration: time::Duration::from_millis(launch.animation),
+            move_entry: None,
+            move_suggestions: Vec::new(),
+            review: None,
+            report: None,
+            tournament: None,
+            match_swapped: false,
         };
 
         Ok(state)
     }
 
-    /// Loads chess piese images into vector.
-    fn load_sprites(ctx: &mut Context) -> HashMap<(u8, u8), graphics::Image> {
+    /// Loads chess piese images from the piece set folder `dir`, falling back to the default set.
+    fn load_sprites(ctx: &mut Context, dir: &str) -> HashMap<(u8, u8), graphics::Image> {
+        let dir = dir.trim_matches('/');
 
         [
-            ((16, 6), "/black_king.png".to_string()),
-            ((16, 5), "/black_queen.png".to_string()),
-            ((16, 4), "/black_rook.png".to_string()),
-            ((16, 1), "/black_pawn.png".to_string()),
-            ((16, 3), "/black_bishop.png".to_string()),
-            ((16, 2), "/black_knight.png".to_string()),
-            ((8, 6), "/white_king.png".to_string()),
-            ((8, 5), "/white_queen.png".to_string()),
-            ((8, 4), "/white_rook.png".to_string()),
-            ((8, 1), "/white_pawn.png".to_string()),
-            ((8, 3), "/white_bishop.png".to_string()),
-            ((8, 2), "/white_knight.png".to_string())
+            ((16, 6), "black_king.png"),
+            ((16, 5), "black_queen.png"),
+            ((16, 4), "black_rook.png"),
+            ((16, 1), "black_pawn.png"),
+            ((16, 3), "black_bishop.png"),
+            ((16, 2), "black_knight.png"),
+            ((8, 6), "white_king.png"),
+            ((8, 5), "white_queen.png"),
+            ((8, 4), "white_rook.png"),
+            ((8, 1), "white_pawn.png"),
+            ((8, 3), "white_bishop.png"),
+            ((8, 2), "white_knight.png")
         ]
             .iter()
-            .map(|(_piece, _path)| {
-                (*_piece, graphics::Image::new(ctx, _path).unwrap())
+            .map(|(_piece, _file)| {
+                let _path = if dir.is_empty() { format!("/{}", _file) } else { format!("/{}/{}", dir, _file) };
+                let image = graphics::Image::new(ctx, &_path).unwrap_or_else(|error| {
+                    println!("Failed to load {}: {}", _path, error);
+                    graphics::Image::new(ctx, format!("/{}", _file)).unwrap()
+                });
+                (*_piece, image)
             })
             .collect::<HashMap<(u8, u8), graphics::Image>>()
     }
 
+    fn theme_colour(rgb: [u8; 3]) -> graphics::Color {
+        graphics::Color::from_rgb(rgb[0], rgb[1], rgb[2])
+    }
+
+    fn light_colour(&self) -> graphics::Color {
+        AppState::theme_colour(self.themes[self.theme].light)
+    }
+
+    fn dark_colour(&self) -> graphics::Color {
+        AppState::theme_colour(self.themes[self.theme].dark)
+    }
+
+    fn highlight_light_colour(&self) -> graphics::Color {
+        AppState::theme_colour(self.themes[self.theme].highlight_light)
+    }
+
+    fn highlight_dark_colour(&self) -> graphics::Color {
+        AppState::theme_colour(self.themes[self.theme].highlight_dark)
+    }
+
+    /// Switches to the next theme, reloading the sprites if it uses another piece set.
+    fn next_theme(&mut self, ctx: &mut Context) -> () {
+        let pieces = self.themes[self.theme].pieces.clone();
+        self.theme = (self.theme + 1) % self.themes.len();
+        println!("Theme: {}", self.themes[self.theme].name);
+        if self.themes[self.theme].pieces != pieces {
+            self.sprites = AppState::load_sprites(ctx, &self.themes[self.theme].pieces);
+        }
+        self.settings.theme = self.themes[self.theme].name.clone();
+        self.save_settings();
+    }
+
+    fn save_settings(&self) -> () {
+        if let Err(error) = self.settings.save() {
+            println!("{}", error);
+        }
+    }
+
+    fn flip_board(&mut self) -> () {
+        self.flipped = !self.flipped;
+        self.settings.flipped = self.flipped;
+        self.save_settings();
+    }
+
+    fn toggle_mute(&mut self) -> () {
+        self.sounds.muted = !self.sounds.muted;
+        self.settings.muted = self.sounds.muted;
+        self.save_settings();
+    }
+
+    fn backend_name() -> String {
+        env::var("CHESS_BACKEND").unwrap_or(DEFAULT_BACKEND.to_string())
+    }
+
+    /// Backend named by `CHESS_BACKEND`, or the default one.
+    fn new_backend() -> Box<dyn ChessBackend> {
+        backend::by_name(&AppState::backend_name()).unwrap_or_else(|error| {
+            println!("{}", error);
+            backend::by_name(DEFAULT_BACKEND).unwrap()
+        })
+    }
+
+    /// Square drawn at board square `sq`'s place, which is its own inverse.
+    fn screen_square(&self, sq: u8) -> u8 {
+        if self.flipped { 63 - sq } else { sq }
+    }
+
+    /// Maps a mouse position in window pixels to layout coordinates.
+    fn to_layout(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * WINDOW_SIZE.0 / self.window_size.0, y * WINDOW_SIZE.1 / self.window_size.1)
+    }
+
     fn create_board(&mut self) -> () {
 
         for _i in 0..64 {
@@ -126,15 +427,8 @@ impl AppState {
 
     }   
 
-    fn convert_string_vector(&mut self, moves:&Vec<String>) -> Vec<Vec<u8>> {
-        let mut int_vector:Vec<Vec<u8>> = Vec::new();
-        for _i in 0..moves.len(){
-            int_vector.push(self.str_to_u8(moves[_i].clone()));
-        }
-        return int_vector;
-    }
-
     fn update_board(&mut self, fen:String) -> () { 
+        let before = self.board.clone();
 
         let mut fen_string:String = "".to_string();
 
@@ -173,6 +467,7 @@ impl AppState {
                 }
             }
         }
+        self.animation = Animation::between(&before, &self.board, self.animation_duration);
 
     }
 
@@ -253,8 +548,21 @@ impl AppState {
     }
      
     fn move_piece(&mut self, pos: u8) -> () {
+        if self.variant_game.is_some() {
+            self.move_custom_piece(pos);
+            return;
+        }
+
         let from = AppState::u8_to_str(self.current_piece[2]);
         let to = AppState::u8_to_str(pos);
+        // The tree and the PGN export are replayed from SAN, so a move without one is refused.
+        let notation = match self.get_san(pos) {
+            Some(san) => san,
+            None => {
+                println!("Illegal move {}{}", from, to);
+                return;
+            }
+        };
         let mut move_pos:String = "".to_string();
         move_pos.push_str(&from);
         move_pos.push_str(" ");
@@ -262,18 +570,18 @@ impl AppState {
         let testS = from.clone();
         //println!("From posistion {}", from);
         //println!("To posistion {}", to);
-        let result = self.game.make_move(from, to.clone());
+        let result = self.game.make_move(&from, &to);
 
         
 
-        if  result != None {
+        if  result {
 
-            self.board[pos as usize][0] = self.board[self.current_piece[2]as usize][0];
-            self.board[pos as usize][1] = self.board[self.current_piece[2] as usize][1];
-            self.board[self.current_piece[2]as usize][0] = 0;
-            self.board[self.current_piece[2]as usize][1] = 0;
-            
-            self.game.set_promotion(to, self.promote_piece);
+            self.game.set_promotion(&to, self.get_promotion_code());
+            let fen = self.game.get_fen();
+            // Taken from the FEN so castling and en passant move every piece involved.
+            self.update_board(fen.clone());
+            self.tree.play(&notation, &fen);
+            self.record.push(notation, fen);
 
             self.current_piece = vec![0,0,64];
             if(self.turn == 8){
@@ -283,43 +591,12 @@ impl AppState {
                 self.turn = 8;
                 self.current_turn = "White".to_string();
             }
+            self.position_changed();
         }
         //println!("{}",self.game.get_fen());
 
     }
 
-    fn str_to_u8(&mut self, pos:String) -> Vec<u8> {
-        let mut _c:String = pos.chars().nth(0).unwrap().to_string();
-        _c = _c.chars().map(|_c| match _c {      
-                'a' => "0", 
-                'b' => "1", 
-                'c' => "2",
-                'd' => "3",
-                'e' => "4",
-                'f' => "5",
-                'g' => "6",
-                'h' => "7",
-                _ => "0"
-            }).collect();
-
-        let mut _s:String  = pos.chars().nth(1).unwrap().to_string();
-
-        _s = _s.chars().map(|_s| match _s {      
-            '1' => "0", 
-            '2' => "1", 
-            '3' => "2",
-            '4' => "3",
-            '5' => "4",
-            '6' => "5",
-            '7' => "6",
-            '8' => "7",
-            _ => "0"
-        }).collect();
-        let _pos1:u8 = _c.parse::<u8>().unwrap();
-        let _pos2:u8 = _s.parse::<u8>().unwrap();
-        return vec![_pos1,_pos2];
-    }
-
     fn get_square(&mut self, x: f32, y: f32) -> u8 {
 
         let mut pos:u8 = 64;
@@ -340,191 +617,1442 @@ impl AppState {
                 }
             }
         }
+        if pos < 64 {
+            pos = self.screen_square(pos);
+        }
         return pos;
     }
 
-}
+    /// Promotion piece as a `rules` piece code. Note that 'k' selects the knight.
+    fn get_promotion_code(&self) -> u8 {
+        match self.promote_piece {
+            'r' => rules::ROOK,
+            'b' => rules::BISHOP,
+            'k' => rules::KNIGHT,
+            _ => rules::QUEEN,
+        }
+    }
 
-impl event::EventHandler<GameError> for AppState {
+    /// Moves the selected piece in a variant game or one started from an edited position.
+    fn move_custom_piece(&mut self, pos: u8) -> () {
+        let promotion = self.get_promotion_code();
+        let variant_game = match self.variant_game.as_mut() {
+            Some(variant_game) => variant_game,
+            None => return,
+        };
 
-    /// For updating game logic, which front-end doesn't handle.
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        Ok(())
+        let legal_move = variant_game.legal_moves_from(self.current_piece[2])
+            .into_iter()
+            .find(|mv| mv.to == pos && (mv.promotion == 0 || mv.promotion == promotion));
+
+        if let Some(mv) = legal_move {
+            let san = variant_game.move_to_san(&mv);
+            variant_game.make_move(mv);
+            let fen = variant_game.position.to_fen();
+            self.tree.play(&san, &fen);
+            self.record.push(san, fen.clone());
+            if variant_game.variant != Variant::Standard {
+                self.record.variant_result = Some(variant_game.result()).filter(|result| result != "*");
+            }
+            self.turn = variant_game.position.turn;
+            self.current_turn = rules::colour_name(variant_game.position.turn);
+            self.current_piece = vec![0,0,64];
+            self.update_board(fen);
+            self.position_changed();
+        }
     }
 
-    /// Draw interface, i.e. draw game board
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+    /// SAN of moving the selected piece to `pos`, if the move is legal.
+    fn get_san(&mut self, pos: u8) -> Option<String> {
+        let position = self.get_current_position();
+        let promotion = self.get_promotion_code();
+        position.legal_moves_from(self.current_piece[2])
+            .into_iter()
+            .find(|mv| mv.to == pos && (mv.promotion == 0 || mv.promotion == promotion))
+            .map(|mv| position.move_to_san(&mv))
+    }
 
-        // clear interface with gray background colour
-        graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
+    /// Destination squares for the piece on `pos`, e.g. "e4".
+    fn get_possible_moves(&mut self, pos: u8) -> Vec<String> {
+        match &self.variant_game {
+            Some(variant_game) => {
+                let mut targets: Vec<String> = variant_game.legal_moves_from(pos).iter().map(|mv| rules::square_name(mv.to)).collect();
+                targets.dedup();
+                targets
+            }
+            None => self.game.get_possible_moves(&AppState::u8_to_str(pos)),
+        }
+    }
 
-        // create text representation
-        let state_text = graphics::Text::new(
-                graphics::TextFragment::from(format!("     Game is:\n    {:?}.", self.game.get_game_state())
-            )
-            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+    fn get_game_state_text(&mut self) -> String {
+        if self.setup.is_some() {
+            return "Setting up".to_string();
+        }
+        if self.replay.is_some() {
+            return "Replaying".to_string();
+        }
+        if let Some((colour, reason)) = self.forfeit {
+            return format!("{} lost {}", rules::colour_name(colour), reason);
+        }
+        match &self.variant_game {
+            Some(variant_game) => variant_game.state_text(),
+            None => self.game.get_game_state(),
+        }
+    }
 
-        // get size of text
-        let text_dimensions = state_text.dimensions(ctx);
-        // create background rectangle with white coulouring
-        let background_box = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(),
-            graphics::Rect::new((SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 as f32 - 8.0,
-                                (SCREEN_SIZE.0 - text_dimensions.h as f32) / 2f32 as f32,
-                                text_dimensions.w as f32 + 16.0, text_dimensions.h as f32),
-                                [1.0, 1.0, 1.0, 1.0].into()
-        )?;
+    fn get_current_fen(&mut self) -> String {
+        match &self.variant_game {
+            Some(variant_game) => variant_game.position.to_fen(),
+            None => self.game.get_fen(),
+        }
+    }
 
-        // draw background
-        graphics::draw(ctx, &background_box, graphics::DrawParam::default()).expect("Failed to draw background.");
+    /// Starts a new game of the selected variant from `position`, using the `ChessBackend` for
+    /// standard chess from the standard start.
+    fn play_from(&mut self, position: Position) -> () {
+        self.game = AppState::new_backend();
+        self.turn = position.turn;
+        self.current_turn = rules::colour_name(position.turn);
+        self.current_piece = vec![0,0,64];
+        let fen = position.to_fen();
+        self.chess960 = None;
+        let standard = self.variant == Variant::Standard && fen == rules::START_FEN && !position.chess960;
+        self.variant_game = if standard { None } else { Some(VariantGame::new(self.variant, position)) };
+        self.record = GameRecord::new(fen.clone());
+        self.tree = MoveTree::new(fen.clone());
+        self.hints = [0, 0];
+        // A replay of the same game keeps what was drawn on it; a new game starts clean.
+        if !self.replaying {
+            self.annotations.clear();
+        }
+        self.forfeit = None;
+        self.premove = None;
+        self.clock = self.time_control.map(Clock::new);
+        self.low_time_warned = [false; 2];
+        for engine in self.player_engines.iter_mut().flatten() {
+            if let Err(error) = engine.stop() {
+                println!("Failed to stop engine: {}", error);
+            }
+            engine.best_move = None;
+        }
+        self.update_board(fen);
+        self.position_changed();
+    }
 
-        // draw grid
-        for _row in 0..8 {
-            for _col in 0..8 {
+    fn get_current_position(&mut self) -> Position {
+        match &self.variant_game {
+            Some(variant_game) => variant_game.position.clone(),
+            None => Position::from_fen(&self.get_current_fen()).unwrap_or(Position::start()),
+        }
+    }
 
-                // draw tile
-                let rectangle = graphics::Mesh::new_rectangle(ctx, 
-                    graphics::DrawMode::fill(), 
-                    graphics::Rect::new_i32(
-                        _col * GRID_CELL_SIZE.0 as i32,
-                        _row * GRID_CELL_SIZE.1 as i32,
-                        GRID_CELL_SIZE.0 as i32,
-                        GRID_CELL_SIZE.1 as i32,
-                    ), match _col % 2 {
-                        0 => 
-                            if _row % 2 == 0 { WHITE } 
-                            else { BLACK },
-                        _ => 
-                            if _row % 2 == 0 { BLACK } 
-                            else { WHITE },
-                    }).expect("Failed to create tile.");
-                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+    fn colour_index(colour: u8) -> usize {
+        if colour == rules::WHITE { 0 } else { 1 }
+    }
 
-            }
+    /// Player name for the PGN headers: the engine's file name, or the colour for a human.
+    fn player_name(&self, colour: u8) -> String {
+        match &self.players[AppState::colour_index(colour)] {
+            Player::Human => rules::colour_name(colour),
+            Player::Engine(path) => path::Path::new(path).file_stem().map_or(path.clone(), |stem| stem.to_string_lossy().to_string()),
         }
+    }
 
-        // Create line between info and board
-        let info_background = graphics::Mesh::new_rectangle(ctx, 
-            graphics::DrawMode::fill(), 
-            graphics::Rect::new_i32(
-                (GRID_CELL_SIZE.0 *8) as i32,
-                    0 as i32,
-                    300 as i32,
-                    (GRID_CELL_SIZE.0 *8) as i32,
-                    ), {BLACK}).expect("Failed to create tile.");
-            
-        // draw line
-        graphics::draw(ctx, &info_background, graphics::DrawParam::default()).expect("Failed to draw background.");
+    /// Whether the game has ended by mate, stalemate, a variant rule or on time.
+    fn game_over(&mut self) -> bool {
+        if self.forfeit.is_some() {
+            return true;
+        }
+        let status = match &self.variant_game {
+            Some(variant_game) if variant_game.winner().is_some() => return true,
+            Some(variant_game) => variant_game.status(),
+            None => Position::from_fen(&self.game.get_fen()).map_or(rules::Status::InProgress, |position| position.status()),
+        };
+        status == rules::Status::Checkmate || status == rules::Status::Stalemate
+    }
 
-        // Create line between info and board
-        let board_line = graphics::Mesh::new_rectangle(ctx, 
-            graphics::DrawMode::fill(), 
-            graphics::Rect::new_i32(
-                (GRID_CELL_SIZE.0 *8) as i32,
-                0 as i32,
-                15 as i32,
-                (GRID_CELL_SIZE.0 *8) as i32,
-            ), {REALBLACK}).expect("Failed to create tile.");
-    
-        // draw line
-        graphics::draw(ctx, &board_line, graphics::DrawParam::default()).expect("Failed to draw background.");
+    /// Ends the game with a loss for `colour` that the board does not show.
+    fn forfeit_game(&mut self, colour: u8, reason: &'static str) -> () {
+        self.forfeit = Some((colour, reason));
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+        self.record.variant_result = Some(if colour == rules::WHITE { "0-1" } else { "1-0" }.to_string());
+        println!("{} lost {}", rules::colour_name(colour), reason);
+        self.sounds.play(Sound::GameEnd);
+    }
 
+    /// Ends the game when the side to move runs out of time.
+    fn check_clock(&mut self) -> () {
+        match self.clock.as_ref().and_then(|clock| clock.flagged()) {
+            Some(colour) if self.forfeit.is_none() => self.forfeit_game(colour, "on time"),
+            _ => {}
+        }
+        let index = AppState::colour_index(self.turn);
+        let low = match &self.clock {
+            Some(clock) => clock.remaining(self.turn) < time::Duration::from_secs(sound::LOW_TIME_SECONDS),
+            None => false,
+        };
+        if low && !self.low_time_warned[index] && self.forfeit.is_none() && !self.game_over() {
+            self.low_time_warned[index] = true;
+            self.sounds.play(Sound::LowTime);
+        }
+    }
 
-        // create text representation
-        let information_text = graphics::Text::new(
-            graphics::TextFragment::from(format!("Information: ")
-            )
-            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+    /// Search limits for an engine player: its clock time, or a fixed time per move.
+    fn engine_limits(&self) -> String {
+        match &self.clock {
+            Some(clock) => format!(
+                "wtime {} btime {} winc {} binc {}",
+                clock.remaining(rules::WHITE).as_millis(),
+                clock.remaining(rules::BLACK).as_millis(),
+                clock.control.increment.as_millis(),
+                clock.control.increment.as_millis(),
+            ),
+            None => format!("movetime {}", ENGINE_MOVE_TIME.as_millis()),
+        }
+    }
 
-        let text_dimensions_info = information_text.dimensions(ctx);
+    /// Starts the engine playing the side to move thinking, and plays its move once it has one.
+    fn play_engine_move(&mut self) -> () {
+        let index = AppState::colour_index(self.turn);
+        if self.player_engines[index].is_none() || self.setup.is_some() || self.trainer.is_some() || self.game_over() {
+            return;
+        }
+        let fen = self.get_current_fen();
+        let chess960 = self.variant_game.as_ref().map_or(false, |variant_game| variant_game.position.chess960);
+        let limits = self.engine_limits();
+
+        let engine = self.player_engines[index].as_mut().unwrap();
+        engine.poll();
+        let best_move = engine.best_move.take();
+        if best_move.is_none() && !engine.is_searching() {
+            if let Err(error) = engine.go(&fen, chess960, &limits) {
+                println!("Engine error: {}", error);
+                self.player_engines[index] = None;
+                self.players[index] = Player::Human;
+            }
+            return;
+        }
 
-        let mut turn_text = graphics::Text::new(
-            graphics::TextFragment::from(format!("Current player:\n     {}", self.current_turn)
-            )
-            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+        if let Some(text) = best_move {
+            let legal = self.get_current_position().parse_uci(&text);
+            if !legal.map_or(false, |mv| self.play_move(mv)) {
+                println!("Engine played illegal move {}", text);
+                if self.tournament.is_some() {
+                    self.forfeit_game(self.turn, "by illegal move");
+                } else {
+                    println!("{} is now human", rules::colour_name(self.turn));
+                    self.player_engines[index] = None;
+                    self.players[index] = Player::Human;
+                }
+            }
+        }
+    }
 
+    /// Legal moves of the current position with their SAN.
+    fn get_legal_moves_with_san(&mut self) -> Vec<(String, rules::Move)> {
+        match &self.variant_game {
+            Some(variant_game) => variant_game.legal_moves().into_iter().map(|mv| (variant_game.move_to_san(&mv), mv)).collect(),
+            None => {
+                let position = self.get_current_position();
+                position.legal_moves().into_iter().map(|mv| (position.move_to_san(&mv), mv)).collect()
+            }
+        }
+    }
 
-        let text_dimensions_turn = turn_text.dimensions(ctx);
+    fn refresh_move_suggestions(&mut self) -> () {
+        let moves = self.get_legal_moves_with_san();
+        self.move_suggestions = match &self.move_entry {
+            Some(entry) => entry.suggestions(&moves),
+            None => Vec::new(),
+        };
+    }
 
-        
-        let mut promote_text = graphics::Text::new(
-            graphics::TextFragment::from(format!("Promotion piece:")
-            )
-            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+    /// Handles a key while a move is being typed. The characters arrive through `text_input_event`.
+    fn move_entry_key(&mut self, keycode: KeyCode) -> () {
+        let moves = self.get_legal_moves_with_san();
+        match keycode {
+            KeyCode::Escape => self.move_entry = None,
+            KeyCode::Back => self.move_entry.as_mut().unwrap().backspace(),
+            KeyCode::Tab => self.move_entry.as_mut().unwrap().complete(&moves),
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                let resolved = self.move_entry.as_ref().unwrap().resolve(&moves);
+                let message = match resolved {
+                    _ if !self.accepting_moves() => "Not your turn".to_string(),
+                    Ok(mv) if self.play_move(mv) => {
+                        self.user_moved();
+                        String::new()
+                    }
+                    Ok(mv) => format!("{} was refused", mv.to_uci()),
+                    Err(message) => message,
+                };
+                if let Some(entry) = self.move_entry.as_mut() {
+                    if message.is_empty() {
+                        entry.text.clear();
+                    }
+                    entry.message = message;
+                }
+            }
+            _ => {}
+        }
+        self.refresh_move_suggestions();
+    }
+
+    /// Marks where the selected piece can go: a dot for a quiet move, a ring for a capture,
+    /// en passant included, and a frame around the square the king castles to.
+    fn draw_move_markers(&mut self, ctx: &mut Context) -> GameResult {
+        let from = self.current_piece[2];
+        if from >= 64 || self.replay.is_some() {
+            return Ok(());
+        }
+        let position = self.get_current_position();
+        for target in self.get_possible_moves(from).iter() {
+            let to = match rules::parse_square(target) {
+                Some(to) => to,
+                None => continue,
+            };
+            let mv = rules::Move { from, to, promotion: 0 };
+            let (x, y) = AppState::square_centre(self.screen_square(to));
+            // The highlight shade of the square's own colour, a1 being dark.
+            let mut colour = if (to % 8 + to / 8) % 2 == 0 { self.highlight_dark_colour() } else { self.highlight_light_colour() };
+            colour.a = MOVE_MARKER_ALPHA;
+            let marker = if position.castling_side(&mv).is_some() {
+                let half = GRID_CELL_SIZE.0 as f32 / 2.0 - 4.0;
+                graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::stroke(8.0),
+                    graphics::Rect::new(x - half, y - half, 2.0 * half, 2.0 * half), colour)?
+            } else if position.is_capture(&mv) {
+                graphics::Mesh::new_circle(ctx, graphics::DrawMode::stroke(8.0), [x, y], 41.0, 0.5, colour)?
+            } else {
+                graphics::Mesh::new_circle(ctx, graphics::DrawMode::fill(), [x, y], 14.0, 0.5, colour)?
+            };
+            graphics::draw(ctx, &marker, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
 
+    /// Captured pieces fading out and moved pieces sliding to their new squares.
+    fn draw_animation(&self, ctx: &mut Context) -> GameResult {
+        let animation = match &self.animation {
+            Some(animation) => animation,
+            None => return Ok(()),
+        };
+        let progress = animation.progress();
+        let corner = |sq: u8| {
+            let screen = self.screen_square(sq);
+            ((screen % 8) as f32 * GRID_CELL_SIZE.0 as f32, (screen / 8) as f32 * GRID_CELL_SIZE.1 as f32)
+        };
+        for (sq, piece) in animation.fades.iter() {
+            let (x, y) = corner(*sq);
+            graphics::draw(ctx, self.sprites.get(piece).unwrap(), graphics::DrawParam::default()
+                .scale([2.0, 2.0])
+                .dest([x, y])
+                .color(graphics::Color::new(1.0, 1.0, 1.0, 1.0 - progress)))?;
+        }
+        for slide in animation.slides.iter() {
+            let (from_x, from_y) = corner(slide.from);
+            let (to_x, to_y) = corner(slide.to);
+            graphics::draw(ctx, self.sprites.get(&slide.piece).unwrap(), graphics::DrawParam::default()
+                .scale([2.0, 2.0])
+                .dest([from_x + (to_x - from_x) * progress, from_y + (to_y - from_y) * progress]))?;
+        }
+        Ok(())
+    }
 
-        let text_dimensions_promote = promote_text.dimensions(ctx);
+    /// Colour of the human who may queue a premove: set while an engine thinks against a human.
+    fn premove_colour(&self) -> Option<u8> {
+        let waiting = rules::opponent(self.turn);
+        let engine_to_move = self.player_engines[AppState::colour_index(self.turn)].is_some();
+        let human_waiting = self.players[AppState::colour_index(waiting)] == Player::Human;
+        if engine_to_move && human_waiting && self.setup.is_none() && self.forfeit.is_none() {
+            Some(waiting)
+        } else {
+            None
+        }
+    }
 
+    /// Handles a board click while the engine thinks: selects one of our pieces, then queues a move.
+    fn premove_click(&mut self, colour: u8, pos: u8) -> () {
+        if self.board[pos as usize][0] == colour {
+            self.current_piece = vec![colour, self.board[pos as usize][1], pos];
+            self.premove = None;
+        } else if self.current_piece[2] < 64 && self.current_piece[0] == colour {
+            self.premove = Some((self.current_piece[2], pos));
+            self.current_piece = vec![0,0,64];
+        } else {
+            self.premove = None;
+        }
+    }
 
-        if self.current_piece[2] < 64 {
-            let mut possible_moves = self.game.get_possible_moves(AppState::u8_to_str(self.current_piece[2]));
-            let draw_pos = self.convert_string_vector(possible_moves.get_or_insert(vec![]));
-            for _i in 0..draw_pos.len(){
-                let selectedRect = graphics::Mesh::new_rectangle(ctx, 
-                    graphics::DrawMode::fill(), 
-                    graphics::Rect::new_i32(
-                        draw_pos[_i][0] as i32 * GRID_CELL_SIZE.0 as i32,
-                        draw_pos[_i][1] as i32 * GRID_CELL_SIZE.1 as i32,
-                        GRID_CELL_SIZE.0 as i32,
-                        GRID_CELL_SIZE.1 as i32,
-                    ), match draw_pos[_i][0] % 2 {
-                        0 => 
-                            if (draw_pos[_i][1]+1) % 2 == 0 { DARKYELLOW } 
-                            else { LIGHTYELLOW },
-                        _ => 
-                            if (draw_pos[_i][1]+1) % 2 == 0 { LIGHTYELLOW } 
-                            else { DARKYELLOW },
-                    }).expect("Failed to create tile.");
-                    graphics::draw(ctx, &selectedRect, graphics::DrawParam::default()).expect("Failed to draw tiles.");
-            }
+    /// Plays the queued premove once it is our turn, dropping it if it is not legal now.
+    fn play_premove(&mut self) -> () {
+        let (from, to) = match self.premove {
+            Some(premove) if self.accepting_moves() && self.premove_colour().is_none() => premove,
+            _ => return,
+        };
+        self.premove = None;
+        let last_rank = to / 8 == 0 || to / 8 == 7;
+        let promotion = if self.board[from as usize][1] == rules::PAWN && last_rank { self.get_promotion_code() } else { 0 };
+        if self.board[from as usize][0] != self.turn || !self.play_move(rules::Move { from, to, promotion }) {
+            println!("Premove {}{} is not legal", rules::square_name(from), rules::square_name(to));
         }
+    }
 
+    /// The `report` command: reviews the first game of a PGN file without a window, prints the
+    /// summary and writes the game with the judgements and evaluations to `--pgn`.
+    fn run_report(file: &path::Path, options: &Options) -> Result<(), String> {
+        let text = std::fs::read_to_string(file).map_err(|error| format!("Failed to read {}: {}", file.display(), error))?;
+        let games = pgn::parse_games(&text);
+        let game = games.first().ok_or(format!("No games in {}", file.display()))?;
+        let mut tree = MoveTree::from_pgn(game)?;
+        let record = tree.record(tree.line_end(0));
+        let chess960 = Position::from_fen(tree.start_fen())?.chess960;
+        let engine_path = env::var("UCI_ENGINE").unwrap_or(DEFAULT_ENGINE.to_string());
+        let report = report::run(&engine_path, record.clone(), options.depth.unwrap_or(report::DEFAULT_DEPTH), chess960)?;
+        for line in report.lines() {
+            println!("{}", line);
+        }
+        report.annotate(&mut tree);
+        // `write_pgn` adds these from the tree.
+        let headers: Vec<(String, String)> = game.headers.iter()
+            .filter(|(name, _)| !["Result", "SetUp", "FEN"].contains(&name.as_str()))
+            .cloned()
+            .collect();
+        let result = game.header("Result").map_or(record.result(), |result| result.to_string());
+        let output = options.pgn.clone().unwrap_or(REPORT_PGN_PATH.to_string());
+        std::fs::write(&output, tree.to_pgn(&headers, &result, &|_| String::new()))
+            .map_err(|error| format!("Failed to write {}: {}", output, error))?;
+        println!("Saved the annotated game to {}", output);
+        Ok(())
+    }
 
+    /// Builds the match for the `match` command from the command line.
+    fn create_tournament(first: &str, second: &str, options: &Options) -> Result<Tournament, String> {
+        let openings = match &options.openings {
+            Some(file) => tournament::load_openings(path::Path::new(file))?,
+            None => vec![rules::START_FEN.to_string()],
+        };
+        Ok(Tournament::new(
+            [first.to_string(), second.to_string()],
+            openings,
+            options.games,
+            options.time.unwrap_or(DEFAULT_MATCH_TIME),
+            options.pgn.clone().unwrap_or(DEFAULT_MATCH_PGN.to_string()),
+        ))
+    }
 
-        for _rows in 0..8 {
-            for _file in 0..8 {
-            //draw piece
-            if self.board[_rows*8 + _file as usize][0] != 0{
+    /// Starts the next game of the match, giving the engines their colours.
+    fn start_match_game(&mut self) -> () {
+        let (fen, swapped) = match &self.tournament {
+            Some(tournament) => tournament.next_game(),
+            None => return,
+        };
+        if swapped != self.match_swapped {
+            self.players.swap(0, 1);
+            self.player_engines.swap(0, 1);
+            self.match_swapped = swapped;
+        }
+        for engine in self.player_engines.iter_mut().flatten() {
+            if let Err(error) = engine.send("ucinewgame") {
+                println!("Engine error: {}", error);
+            }
+        }
+        self.play_from(Position::from_fen(&fen).unwrap());
+    }
 
-                if self.current_piece[2] == (_rows*8 + _file) as u8{
-                    graphics::draw(ctx, self.sprites.get(&(self.board[_rows*8 + _file as usize][0], self.board[_rows*8 + _file  as usize][1])).unwrap(), graphics::DrawParam::default()
-                    .scale([2.0, 2.0])  // Tile size is 90 pixels, while image sizes are 45 pixels.
-                    .dest(
-                        [_file as f32 * GRID_CELL_SIZE.0 as f32, _rows as f32 * GRID_CELL_SIZE.1 as f32 - 12 as f32],
-                        )
-                    ).expect("Failed to draw piece.");
-                }else{
-                    graphics::draw(ctx, self.sprites.get(&(self.board[_rows*8 + _file as usize][0], self.board[_rows*8 + _file  as usize][1])).unwrap(), graphics::DrawParam::default()
-                    .scale([2.0, 2.0])  // Tile size is 90 pixels, while image sizes are 45 pixels.
-                    .dest(
-                        [_file as f32 * GRID_CELL_SIZE.0 as f32, _rows as f32 * GRID_CELL_SIZE.1 as f32],
-                        )
-                    ).expect("Failed to draw piece.");
-                    }
+    /// Records a finished match game and starts the next one.
+    fn advance_tournament(&mut self) -> () {
+        if self.tournament.as_ref().map_or(true, |tournament| tournament.finished()) {
+            return;
+        }
+        if !self.game_over() {
+            match tournament::adjudicate(&self.record) {
+                Some((result, reason)) => {
+                    println!("Draw by {}", reason);
+                    self.record.variant_result = Some(result);
                 }
+                None => return,
             }
         }
 
+        let tournament = self.tournament.as_mut().unwrap();
+        tournament.record_game(&self.record, self.match_swapped);
+        if tournament.finished() {
+            println!("Final score of {} vs {}: {}", tournament.engines[0], tournament.engines[1], tournament.score.to_text());
+        } else {
+            self.start_match_game();
+        }
+    }
 
-        // draw text with dark gray colouring and center position
-        graphics::draw(ctx, &state_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
-            .dest(ggez::mint::Point2 {
-                x: ((SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
-                y: (text_dimensions.h as f32+250.0) / 2f32 as f32,
-            })).expect("Failed to draw text.");
-    
-        // draw text with dark gray colouring and center position
-        graphics::draw(ctx, &information_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
-        .dest(ggez::mint::Point2 {
-            x: ((SCREEN_SIZE.0 - text_dimensions_info.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
-            y: (text_dimensions_info.h as f32+30.0) / 2f32 as f32,
-        })).expect("Failed to draw text.");
+    /// Sets the players, starting engines for the engine players.
+    fn start_players(&mut self, white: Player, black: Player) -> Result<(), String> {
+        self.players = [white, black];
+        for index in 0..2 {
+            self.player_engines[index] = match &self.players[index] {
+                Player::Engine(path) => Some(Engine::start(path).map_err(|error| format!("Failed to start engine {}: {}", path, error))?),
+                Player::Human => None,
+            };
+        }
+        Ok(())
+    }
 
-        // draw text with dark gray colouring and center position
-        graphics::draw(ctx, &turn_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
-            .dest(ggez::mint::Point2 {
-                x: ((SCREEN_SIZE.0 - text_dimensions_turn.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
-                y: (text_dimensions_turn.h as f32+90.0) / 2f32 as f32,
-            })).expect("Failed to draw text.");
+    /// Applies the command line: players, clock, start position and the command to run.
+    fn apply_options(&mut self, options: &Options) -> Result<(), String> {
+        if let Command::Match(first, second) = &options.command {
+            let tournament = AppState::create_tournament(first, second, options)?;
+            self.time_control = Some(tournament.control);
+            self.tournament = Some(tournament);
+            self.start_players(Player::Engine(first.clone()), Player::Engine(second.clone()))?;
+            self.start_match_game();
+            return Ok(());
+        }
+
+        self.start_players(options.white.clone(), options.black.clone())?;
+        self.time_control = options.time;
+
+        let position = match &options.fen {
+            Some(fen) => {
+                let position = Position::from_fen(fen)?;
+                position.validate()?;
+                position
+            }
+            None => Position::start(),
+        };
+        self.play_from(position);
+
+        match &options.command {
+            Command::Setup => self.start_setup(),
+            Command::Replay(file) => self.open_replay(path::Path::new(file))?,
+            Command::Analyze(file) => self.load_for_analysis(path::Path::new(file))?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads the first game in a PGN file with its variations, goes to the end of the main line
+    /// and turns on analysis.
+    fn load_for_analysis(&mut self, file: &path::Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(file).map_err(|error| format!("Failed to read {}: {}", file.display(), error))?;
+        let games = pgn::parse_games(&text);
+        let game = games.first().ok_or(format!("No games in {}", file.display()))?;
+        let tree = MoveTree::from_pgn(game)?;
+        self.play_from(Position::from_fen(tree.start_fen())?);
+        self.tree = tree;
+        let end = self.tree.line_end(0);
+        self.rebuild_to(end);
+        if self.engine.is_none() {
+            self.toggle_analysis();
+        }
+        Ok(())
+    }
+
+    /// Switches to the next variant and starts a new game of it.
+    fn next_variant(&mut self) -> () {
+        self.variant = self.variant.next();
+        self.trainer = None;
+        self.puzzle_reply = None;
+        println!("Variant: {}", self.variant.name());
+        self.play_from(Position::start());
+    }
+
+    /// Starts a Chess960 game from start position `number`, or a random one.
+    fn start_chess960(&mut self, number: Option<u16>) -> () {
+        let number = number.unwrap_or_else(|| {
+            let nanos = time::SystemTime::now().duration_since(time::UNIX_EPOCH).map_or(0, |since| since.subsec_nanos());
+            (nanos % 960) as u16
+        });
+        self.trainer = None;
+        self.play_from(Position::chess960(number));
+        self.chess960 = Some(number);
+        println!("Chess960 position {}: {}", number, self.get_current_fen());
+    }
+
+    /// Called after every change of the played position.
+    fn position_changed(&mut self) -> () {
+        self.hint = None;
+        if let Some(engine) = self.hint_engine.as_mut() {
+            if let Err(error) = engine.stop() {
+                println!("Failed to stop engine: {}", error);
+            }
+            // A reply that arrived before the stop was for the old position.
+            engine.best_move = None;
+        }
+        let game_over = self.game_over();
+        if let Some(san) = self.record.moves.last() {
+            self.sounds.play(Sound::for_move(san, game_over));
+        }
+        if game_over {
+            if let Some(clock) = self.clock.as_mut() {
+                clock.stop();
+            }
+        } else if let Some(clock) = self.clock.as_mut() {
+            clock.switch(self.turn);
+        }
+        self.restart_analysis();
+        self.refresh_explorer();
+        self.opening = self.eco.classify(&self.record.fens).cloned();
+        if !self.replaying {
+            self.validate_moves();
+        }
+    }
+
+    /// With `VALIDATE_MOVES` set, compares the backend's moves with the reference generator and
+    /// writes a bug report on a mismatch.
+    fn validate_moves(&mut self) -> () {
+        let dir = match (&self.validate_dir, &self.variant_game) {
+            (Some(dir), None) => dir.clone(),
+            _ => return,
+        };
+        let discrepancies = match validate::compare(self.game.as_ref()) {
+            Ok(discrepancies) => discrepancies,
+            Err(error) => {
+                println!("Cannot validate moves: {}", error);
+                return;
+            }
+        };
+        if discrepancies.is_empty() {
+            return;
+        }
+        let fen = self.game.get_fen();
+        println!("The {} backend's moves differ from the reference in {}", AppState::backend_name(), fen);
+        let report = validate::report(&AppState::backend_name(), &fen, &self.record.moves, &discrepancies);
+        print!("{}", report);
+        match validate::write_report(&dir, &report) {
+            Ok(file) => println!("Bug report written to {}", file.display()),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    /// Turns the opening explorer on or off, importing the games folder on first use.
+    fn toggle_explorer(&mut self) -> () {
+        if self.explorer.is_some() {
+            self.explorer = None;
+            self.explorer_moves.clear();
+            return;
+        }
+        let dir = env::var("EXPLORER_DIR").unwrap_or(DEFAULT_EXPLORER_DIR.to_string());
+        match Explorer::open(path::Path::new(&dir)) {
+            Ok(explorer) => {
+                self.explorer = Some(explorer);
+                self.refresh_explorer();
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    fn refresh_explorer(&mut self) -> () {
+        if self.explorer.is_none() {
+            return;
+        }
+        let position = self.get_current_position();
+        self.explorer_moves = self.explorer.as_ref().unwrap().moves(&position);
+    }
+
+    /// Plays the explorer move in the clicked row.
+    fn explorer_click(&mut self, y: f32) -> () {
+        if y < EXPLORER_ROWS_Y {
+            return;
+        }
+        let row = ((y - EXPLORER_ROWS_Y) / PANEL_LINE_HEIGHT) as usize;
+        let san = match self.explorer_moves.get(row) {
+            Some((san, _)) if row < EXPLORER_ROWS => san.clone(),
+            _ => return,
+        };
+        let position = self.get_current_position();
+        if let Some(mv) = position.parse_san(&san) {
+            self.play_move(mv);
+        }
+    }
+
+    /// Draws the explorer moves with their game count and white/draw/black percentages.
+    fn draw_explorer_panel(&self, ctx: &mut Context) -> GameResult {
+        let games = self.explorer.as_ref().map_or(0, |explorer| explorer.games);
+        AppState::draw_panel_text(ctx, &format!("Explorer ({} games)", games), EXPLORER_ROWS_Y - 35.0, 24.0)?;
+        if self.explorer_moves.is_empty() {
+            return AppState::draw_panel_text(ctx, "No games from here", EXPLORER_ROWS_Y, 20.0);
+        }
+
+        let mut line_y = EXPLORER_ROWS_Y;
+        for (san, stats) in self.explorer_moves.iter().take(EXPLORER_ROWS) {
+            let (white, draws, black) = stats.percentages();
+            for (text, column) in [
+                (san.clone(), 0.0),
+                (stats.total().to_string(), 70.0),
+                (format!("{}/{}/{}%", white, draws, black), 135.0),
+            ].iter() {
+                let cell = graphics::Text::new(graphics::TextFragment::from(text.as_str()).scale(graphics::PxScale { x: 20.0, y: 20.0 }));
+                graphics::draw(ctx, &cell, graphics::DrawParam::default().color(REALBLACK)
+                    .dest([SCREEN_SIZE.0 + 25.0 + column, line_y]))?;
+            }
+            line_y += PANEL_LINE_HEIGHT;
+        }
+        Ok(())
+    }
+
+    /// Opens the replay viewer on `file`. The live game is paused, not changed.
+    fn open_replay(&mut self, file: &path::Path) -> Result<(), String> {
+        let replay = Replay::load(file)?;
+        println!("Loaded {} games from {}", replay.games.len(), file.display());
+        let fen = replay.fen().to_string();
+        self.replay = Some(replay);
+        self.update_board(fen);
+        // The game waits while the viewer is open, its clock included.
+        if let Some(clock) = self.clock.as_mut() {
+            self.clock_paused = clock.is_running();
+            clock.stop();
+        }
+        Ok(())
+    }
+
+    /// Opens the replay viewer on `REPLAY_FILE`, or closes it and shows the live game again.
+    fn toggle_replay(&mut self) -> () {
+        if self.replay.is_some() {
+            self.replay = None;
+            let fen = self.get_current_fen();
+            self.update_board(fen);
+            if let (Some(clock), true) = (self.clock.as_mut(), self.clock_paused) {
+                clock.switch(self.turn);
+            }
+            self.clock_paused = false;
+            return;
+        }
+        let file = env::var("REPLAY_FILE").unwrap_or(DEFAULT_REPLAY_FILE.to_string());
+        if let Err(error) = self.open_replay(path::Path::new(&file)) {
+            println!("{}", error);
+        }
+    }
+
+    /// Handles a key while the replay viewer is open.
+    fn replay_key(&mut self, keycode: KeyCode) -> () {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => return,
+        };
+        match (replay.viewing, keycode) {
+            (_, KeyCode::R) => return self.toggle_replay(),
+            (false, KeyCode::Escape) => return self.toggle_replay(),
+            (false, KeyCode::Up) => replay.select(-1),
+            (false, KeyCode::Down) => replay.select(1),
+            (false, KeyCode::PageUp) => replay.select(-(EXPLORER_ROWS as isize)),
+            (false, KeyCode::PageDown) => replay.select(EXPLORER_ROWS as isize),
+            (false, KeyCode::Return) => replay.open(),
+            (true, KeyCode::Escape) | (true, KeyCode::Back) => replay.close(),
+            (true, KeyCode::Left) => replay.step(false),
+            (true, KeyCode::Right) => replay.step(true),
+            (true, KeyCode::Home) => replay.go_to(0),
+            (true, KeyCode::End) => replay.go_to(usize::MAX),
+            (true, KeyCode::Space) => replay.toggle_autoplay(),
+            (true, KeyCode::Equals) | (true, KeyCode::Plus) => replay.change_speed(true),
+            (true, KeyCode::Minus) => replay.change_speed(false),
+            _ => return,
+        }
+        let fen = replay.fen().to_string();
+        self.update_board(fen);
+    }
+
+    /// Opens the game in the clicked row of the replay list.
+    fn replay_click(&mut self, y: f32) -> () {
+        let replay = match self.replay.as_mut() {
+            Some(replay) if !replay.viewing && y >= EXPLORER_ROWS_Y => replay,
+            _ => return,
+        };
+        if let Some(index) = replay.game_at_row(((y - EXPLORER_ROWS_Y) / PANEL_LINE_HEIGHT) as usize, EXPLORER_ROWS) {
+            replay.selected = index;
+            replay.open();
+            let fen = replay.fen().to_string();
+            self.update_board(fen);
+        }
+    }
+
+    /// Draws the replay game list, or the headers of the game being replayed.
+    fn draw_replay_panel(&self, ctx: &mut Context) -> GameResult {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return Ok(()),
+        };
+        let title = if replay.viewing { "Game" } else { "Games" };
+        AppState::draw_panel_text(ctx, title, EXPLORER_ROWS_Y - 35.0, 24.0)?;
+        let mut line_y = EXPLORER_ROWS_Y;
+        for row in replay.rows(EXPLORER_ROWS).iter() {
+            AppState::draw_panel_text(ctx, &AppState::wrap_text(row, 26)[0], line_y, 20.0)?;
+            line_y += PANEL_LINE_HEIGHT;
+        }
+        Ok(())
+    }
+
+    /// Whether moves may be taken back, which a running clock, match or puzzle does not allow.
+    fn can_navigate(&self) -> bool {
+        self.clock.is_none() && self.tournament.is_none() && self.trainer.is_none()
+            && self.player_engines.iter().all(Option::is_none)
+    }
+
+    /// Goes to `node` of the move tree, if there is one and moves may be taken back.
+    fn navigate(&mut self, node: Option<usize>) -> () {
+        match node {
+            Some(node) if node != self.tree.current && self.can_navigate() => self.rebuild_to(node),
+            _ => {}
+        }
+    }
+
+    /// Deletes the variation holding the current move and goes to the move before it.
+    fn delete_variation(&mut self) -> () {
+        if self.can_navigate() && self.tree.delete() {
+            let node = self.tree.current;
+            self.rebuild_to(node);
+        }
+    }
+
+    /// Plays the game again from the start along the tree to `node`, keeping the tree, so the
+    /// backend, record and board all match the new current move.
+    fn rebuild_to(&mut self, node: usize) -> () {
+        let tree = self.tree.clone();
+        let before = self.board.clone();
+        let chess960 = self.chess960;
+        let hints = self.hints;
+        let start = match chess960 {
+            Some(number) => Position::chess960(number),
+            None => Position::from_fen(tree.start_fen()).unwrap_or(Position::start()),
+        };
+        let muted = self.sounds.muted;
+        self.sounds.muted = true;
+        self.replaying = true;
+        self.play_from(start);
+        self.chess960 = chess960;
+        self.hints = hints;
+        self.tree = tree;
+        self.tree.current = 0;
+        for step in self.tree.path(node) {
+            let san = self.tree.nodes[step].san.clone();
+            let mv = self.get_current_position().parse_san(&san);
+            if !mv.map_or(false, |mv| self.play_move(mv)) {
+                println!("Stopping at illegal move {}", san);
+                break;
+            }
+        }
+        self.replaying = false;
+        self.validate_moves();
+        self.sounds.muted = muted;
+        self.animation = Animation::between(&before, &self.board, self.animation_duration);
+    }
+
+    /// Handles a key while a comment is being typed. The characters arrive through `text_input_event`.
+    fn comment_entry_key(&mut self, keycode: KeyCode) -> () {
+        match keycode {
+            KeyCode::Escape => self.comment_entry = None,
+            KeyCode::Back => {
+                self.comment_entry.as_mut().unwrap().pop();
+            }
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                let text = self.comment_entry.take().unwrap();
+                let current = self.tree.current;
+                self.tree.nodes[current].comment = text.trim().to_string();
+            }
+            _ => {}
+        }
+    }
+
+    /// Draws the move tree with its variations, scrolled to the current move in brackets.
+    fn draw_moves_panel(&self, ctx: &mut Context) -> GameResult {
+        AppState::draw_panel_text(ctx, "Moves", EXPLORER_ROWS_Y - 35.0, 24.0)?;
+        let lines = AppState::wrap_text(&self.tree.display_text(), 26);
+        if lines.is_empty() {
+            return AppState::draw_panel_text(ctx, "No moves yet", EXPLORER_ROWS_Y, 20.0);
+        }
+        let marker = format!("[{}", self.tree.nodes[self.tree.current].san);
+        let current = lines.iter().position(|line| self.tree.current != 0 && line.contains(&marker)).unwrap_or(0);
+        let mut line_y = EXPLORER_ROWS_Y;
+        for line in lines.iter().skip((current + 1).saturating_sub(EXPLORER_ROWS)).take(EXPLORER_ROWS) {
+            AppState::draw_panel_text(ctx, line, line_y, 20.0)?;
+            line_y += PANEL_LINE_HEIGHT;
+        }
+        Ok(())
+    }
+
+    /// Shows the piece to move for a hint, then on a second press its target square. The best
+    /// move comes from the UCI engine when `UCI_ENGINE` is set, else from the built-in search.
+    fn request_hint(&mut self) -> () {
+        if let Some((mv, _)) = self.hint {
+            self.hint = Some((mv, true));
+            return;
+        }
+        if self.hint_engine.as_ref().map_or(false, Engine::is_searching) || self.game_over() {
+            return;
+        }
+        if self.variant_game.as_ref().map_or(false, |variant_game| variant_game.variant != Variant::Standard) {
+            println!("Hints are only given in standard chess");
+            return;
+        }
+        let position = self.get_current_position();
+        if env::var("UCI_ENGINE").is_ok() {
+            if self.hint_engine.is_none() {
+                match Engine::start(&self.engine_path) {
+                    Ok(engine) => self.hint_engine = Some(engine),
+                    Err(error) => println!("Failed to start engine {}, using the built-in search: {}", self.engine_path, error),
+                }
+            }
+            if let Some(engine) = self.hint_engine.as_mut() {
+                let limits = format!("movetime {}", HINT_MOVE_TIME.as_millis());
+                match engine.go(&position.to_fen(), position.chess960, &limits) {
+                    Ok(_) => return,
+                    Err(error) => println!("Failed to ask the engine for a hint: {}", error),
+                }
+            }
+        }
+        if let Some(mv) = search::best_move(&position, search::DEFAULT_DEPTH) {
+            self.show_hint(mv);
+        }
+    }
+
+    fn show_hint(&mut self, mv: rules::Move) -> () {
+        self.hint = Some((mv, false));
+        self.hints[AppState::colour_index(self.turn)] += 1;
+    }
+
+    /// Shows the hint engine's move once it has finished thinking.
+    fn poll_hint(&mut self) -> () {
+        let best_move = match self.hint_engine.as_mut() {
+            Some(engine) => {
+                engine.poll();
+                engine.best_move.take()
+            }
+            None => return,
+        };
+        if let Some(mv) = best_move.and_then(|text| self.get_current_position().parse_uci(&text)) {
+            self.show_hint(mv);
+        }
+    }
+
+    /// Starts a post-game review of the main line with the analysis engine, or closes the report.
+    fn toggle_report(&mut self) -> () {
+        if self.review.is_some() || self.report.is_some() {
+            self.review = None;
+            self.report = None;
+            return;
+        }
+        let record = self.tree.record(self.tree.line_end(0));
+        let chess960 = self.variant_game.as_ref().map_or(false, |variant_game| variant_game.position.chess960);
+        match Review::start(&self.engine_path, record, report::DEFAULT_DEPTH, chess960) {
+            Ok(review) => self.review = Some((review, self.tree.clone())),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    /// Advances the running review. Once it is done the report is shown and the annotated
+    /// game saved to `REPORT_PGN_PATH`.
+    fn poll_review(&mut self) -> () {
+        let result = match self.review.as_mut() {
+            Some((review, _)) => review.poll(),
+            None => return,
+        };
+        match result {
+            Ok(None) => {}
+            Ok(Some(report)) => {
+                let (_, mut tree) = self.review.take().unwrap();
+                report.annotate(&mut tree);
+                let pgn = tree.to_pgn(&self.pgn_headers(), &self.main_line_result(), &|_| String::new());
+                match std::fs::write(REPORT_PGN_PATH, pgn) {
+                    Ok(_) => println!("Saved the annotated game to {}", REPORT_PGN_PATH),
+                    Err(error) => println!("Failed to save {}: {}", REPORT_PGN_PATH, error),
+                }
+                self.report = Some(report);
+            }
+            Err(error) => {
+                println!("Review failed: {}", error);
+                self.review = None;
+            }
+        }
+    }
+
+    /// Draws the evaluation graph of the report with the current move marked, and the
+    /// accuracy and judged moves below it. While the review runs, its progress.
+    fn draw_report_panel(&self, ctx: &mut Context) -> GameResult {
+        AppState::draw_panel_text(ctx, "Report", EXPLORER_ROWS_Y - 35.0, 24.0)?;
+        let report = match (&self.report, &self.review) {
+            (Some(report), _) => report,
+            (None, Some((review, _))) => {
+                let (done, total) = review.progress();
+                return AppState::draw_panel_text(ctx, &format!("Evaluating {}/{}", done, total), EXPLORER_ROWS_Y, 20.0);
+            }
+            (None, None) => return Ok(()),
+        };
+
+        let (x, y, width) = (SCREEN_SIZE.0 + 25.0, EXPLORER_ROWS_Y, 250.0);
+        let background = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(),
+            graphics::Rect::new(x, y, width, REPORT_GRAPH_HEIGHT), [0.2, 0.2, 0.2, 1.0].into())?;
+        graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+        let middle = graphics::Mesh::new_line(ctx, &[[x, y + REPORT_GRAPH_HEIGHT / 2.0], [x + width, y + REPORT_GRAPH_HEIGHT / 2.0]],
+            1.0, [0.5, 0.5, 0.5, 1.0].into())?;
+        graphics::draw(ctx, &middle, graphics::DrawParam::default())?;
+        // White's winning chance, with White's advantage upwards.
+        let step = width / (report.evals.len() - 1).max(1) as f32;
+        let points: Vec<[f32; 2]> = report.evals.iter().enumerate()
+            .map(|(i, eval)| [x + i as f32 * step, y + REPORT_GRAPH_HEIGHT * (1.0 - eval.winning_chance())])
+            .collect();
+        if points.len() > 1 {
+            let graph = graphics::Mesh::new_line(ctx, &points, 2.0, [1.0, 1.0, 1.0, 1.0].into())?;
+            graphics::draw(ctx, &graph, graphics::DrawParam::default())?;
+        }
+        let ply = self.record.moves.len();
+        if ply < report.evals.len() {
+            let marker_x = x + ply as f32 * step;
+            let marker = graphics::Mesh::new_line(ctx, &[[marker_x, y], [marker_x, y + REPORT_GRAPH_HEIGHT]], 2.0, BEST_MOVE_COLOUR)?;
+            graphics::draw(ctx, &marker, graphics::DrawParam::default())?;
+        }
+
+        let rows = ((EXPLORER_ROWS as f32 * PANEL_LINE_HEIGHT - REPORT_GRAPH_HEIGHT) / PANEL_LINE_HEIGHT) as usize;
+        let mut line_y = y + REPORT_GRAPH_HEIGHT + 8.0;
+        for line in report.lines().iter().take(rows) {
+            AppState::draw_panel_text(ctx, &AppState::wrap_text(line, 26)[0], line_y, 20.0)?;
+            line_y += PANEL_LINE_HEIGHT;
+        }
+        Ok(())
+    }
+
+    /// Turns analysis mode on or off.
+    fn toggle_analysis(&mut self) -> () {
+        if self.engine.is_some() {
+            self.engine = None;
+            self.analysis_lines.clear();
+            return;
+        }
+        match Engine::start(&self.engine_path) {
+            Ok(engine) => {
+                self.engine = Some(engine);
+                self.restart_analysis();
+            }
+            Err(error) => println!("Failed to start engine {}: {}", self.engine_path, error),
+        }
+    }
+
+    /// Restarts the infinite search on the current position.
+    fn restart_analysis(&mut self) -> () {
+        if self.engine.is_none() {
+            return;
+        }
+        let fen = self.get_current_fen();
+        let chess960 = self.variant_game.as_ref().map_or(false, |variant_game| variant_game.position.chess960);
+        self.analysis_lines = vec!["Analysing...".to_string()];
+        if let Some(engine) = self.engine.as_mut() {
+            if let Err(error) = engine.analyse(&fen, chess960) {
+                println!("Engine stopped responding: {}", error);
+                self.engine = None;
+                self.analysis_lines.clear();
+            }
+        }
+    }
+
+    /// Reads new engine output and rebuilds the analysis text.
+    fn poll_analysis(&mut self) -> () {
+        let changed = match self.engine.as_mut() {
+            Some(engine) => engine.poll(),
+            None => return,
+        };
+        if !changed {
+            return;
+        }
+        let position = self.get_current_position();
+        let analysis = self.engine.as_ref().unwrap().analysis.clone();
+        let score = analysis.score.map_or("?".to_string(), |score| score.for_white(position.turn == rules::WHITE).to_text());
+        self.analysis_lines = vec![format!("Depth {}   {}", analysis.depth, score)];
+        self.analysis_lines.extend(AppState::wrap_text(&position.line_to_san(&analysis.pv), 26));
+    }
+
+    /// Splits `text` into lines of at most `width` characters at spaces.
+    fn wrap_text(text: &str, width: usize) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            if !line.is_empty() && line.len() + word.len() + 1 > width {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Draws the evaluation bar from White's point of view, White's share on White's side of the board.
+    fn draw_eval_bar(&mut self, ctx: &mut Context) -> GameResult {
+        let white_to_move = self.turn == rules::WHITE;
+        let white_share = match self.engine.as_ref().and_then(|engine| engine.analysis.score) {
+            Some(score) => score.for_white(white_to_move).winning_chance(),
+            None => 0.5,
+        };
+        let height = SCREEN_SIZE.1 * white_share;
+        let top = if self.flipped { SCREEN_SIZE.1 - height } else { 0.0 };
+        let white_bar = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(SCREEN_SIZE.0, top, EVAL_BAR_WIDTH, height),
+            [1.0, 1.0, 1.0, 1.0].into()).expect("Failed to create evaluation bar.");
+        graphics::draw(ctx, &white_bar, graphics::DrawParam::default())
+    }
+
+    /// Centre of a square in screen coordinates, `sq` being the square's place on screen.
+    fn square_centre(sq: u8) -> (f32, f32) {
+        (
+            (sq % 8) as f32 * GRID_CELL_SIZE.0 as f32 + GRID_CELL_SIZE.0 as f32 / 2.0,
+            (sq / 8) as f32 * GRID_CELL_SIZE.1 as f32 + GRID_CELL_SIZE.1 as f32 / 2.0,
+        )
+    }
+
+    fn annotation_colour(colour: char) -> graphics::Color {
+        match colour {
+            'R' => graphics::Color::new(0.75, 0.1, 0.1, 0.7),
+            'Y' => graphics::Color::new(0.9, 0.7, 0.0, 0.7),
+            'B' => graphics::Color::new(0.0, 0.3, 0.7, 0.7),
+            _ => graphics::Color::new(0.1, 0.55, 0.15, 0.7),
+        }
+    }
+
+    /// Annotation colour chosen by the held modifier key, green without one.
+    fn get_annotation_colour(ctx: &Context) -> char {
+        if keyboard::is_mod_active(ctx, KeyMods::SHIFT) {
+            'R'
+        } else if keyboard::is_mod_active(ctx, KeyMods::ALT) {
+            'Y'
+        } else if keyboard::is_mod_active(ctx, KeyMods::CTRL) {
+            'B'
+        } else {
+            'G'
+        }
+    }
+
+    /// Finishes a right-click drag: a circle if it ended where it started, otherwise an arrow.
+    fn annotate(&mut self, ctx: &Context, pos: u8) -> () {
+        let start = match self.arrow_start.take() {
+            Some(start) => start,
+            None => return,
+        };
+        if pos >= 64 {
+            return;
+        }
+        let colour = AppState::get_annotation_colour(ctx);
+        let key = rules::position_key(self.record.current_fen());
+        let annotations = self.annotations.entry(key.clone()).or_insert(Annotations::default());
+        if start == pos {
+            annotations.toggle_circle(colour, pos);
+        } else {
+            annotations.toggle_arrow(colour, start, pos);
+        }
+        if annotations.is_empty() {
+            self.annotations.remove(&key);
+        }
+    }
+
+    fn draw_arrow(ctx: &mut Context, from: u8, to: u8, colour: graphics::Color) -> GameResult {
+        let (from_x, from_y) = AppState::square_centre(from);
+        let (to_x, to_y) = AppState::square_centre(to);
+        let length = ((to_x - from_x).powi(2) + (to_y - from_y).powi(2)).sqrt();
+        let (dir_x, dir_y) = ((to_x - from_x) / length, (to_y - from_y) / length);
+        let head_base = [to_x - dir_x * 30.0, to_y - dir_y * 30.0];
+
+        let shaft = graphics::Mesh::new_line(ctx, &[[from_x, from_y], head_base], 14.0, colour)?;
+        let head = graphics::Mesh::new_polygon(ctx, graphics::DrawMode::fill(), &[
+            [to_x, to_y],
+            [head_base[0] - dir_y * 20.0, head_base[1] + dir_x * 20.0],
+            [head_base[0] + dir_y * 20.0, head_base[1] - dir_x * 20.0],
+        ], colour)?;
+        graphics::draw(ctx, &shaft, graphics::DrawParam::default())?;
+        graphics::draw(ctx, &head, graphics::DrawParam::default())
+    }
+
+    /// Draws the user's arrows and circles for the current position, and the engine's best move.
+    fn draw_annotations(&self, ctx: &mut Context) -> GameResult {
+        if let Some(annotations) = self.annotations.get(&rules::position_key(self.record.current_fen())) {
+            for (colour, square) in annotations.circles.iter() {
+                let (centre_x, centre_y) = AppState::square_centre(self.screen_square(*square));
+                let circle = graphics::Mesh::new_circle(ctx, graphics::DrawMode::stroke(6.0),
+                    [centre_x, centre_y], 40.0, 0.5, AppState::annotation_colour(*colour))?;
+                graphics::draw(ctx, &circle, graphics::DrawParam::default())?;
+            }
+            for (colour, from, to) in annotations.arrows.iter() {
+                AppState::draw_arrow(ctx, self.screen_square(*from), self.screen_square(*to), AppState::annotation_colour(*colour))?;
+            }
+        }
+
+        if self.show_best_move {
+            let best_move = self.engine.as_ref().and_then(|engine| engine.analysis.pv.first());
+            if let Some(best_move) = best_move {
+                let from = best_move.get(0..2).and_then(rules::parse_square);
+                let to = best_move.get(2..4).and_then(rules::parse_square);
+                if let (Some(from), Some(to)) = (from, to) {
+                    AppState::draw_arrow(ctx, self.screen_square(from), self.screen_square(to), BEST_MOVE_COLOUR)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the game with its variations, comments and annotations to `PGN_EXPORT_PATH`.
+    fn export_pgn(&mut self) -> () {
+        let annotations = &self.annotations;
+        let comment = |fen: &str| annotations.get(&rules::position_key(fen)).map_or(String::new(), |a| a.to_pgn_comment());
+        let pgn = self.tree.to_pgn(&self.pgn_headers(), &self.main_line_result(), &comment);
+        match std::fs::write(PGN_EXPORT_PATH, pgn) {
+            Ok(_) => println!("Saved game to {}", PGN_EXPORT_PATH),
+            Err(error) => println!("Failed to save {}: {}", PGN_EXPORT_PATH, error),
+        }
+    }
+
+    /// PGN headers of the game, apart from the result and start position.
+    fn pgn_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![
+            ("Event".to_string(), "Casual game".to_string()),
+            ("Site".to_string(), "ogronman-chess-gui".to_string()),
+            ("Date".to_string(), "????.??.??".to_string()),
+            ("Round".to_string(), "-".to_string()),
+            ("White".to_string(), self.player_name(rules::WHITE)),
+            ("Black".to_string(), self.player_name(rules::BLACK)),
+        ];
+        if let Some(control) = &self.time_control {
+            headers.push(("TimeControl".to_string(), format!("{}+{}", control.base.as_secs(), control.increment.as_secs())));
+        }
+        match &self.variant_game {
+            Some(variant_game) if variant_game.variant != Variant::Standard => {
+                headers.push(("Variant".to_string(), variant_game.variant.name().to_string()));
+            }
+            Some(variant_game) if variant_game.position.chess960 => {
+                headers.push(("Variant".to_string(), "Chess960".to_string()));
+            }
+            _ => {}
+        }
+        if let Some(opening) = &self.opening {
+            headers.push(("ECO".to_string(), opening.eco.clone()));
+            headers.push(("Opening".to_string(), opening.name.clone()));
+        }
+        if self.hints != [0, 0] {
+            headers.push(("WhiteHints".to_string(), self.hints[0].to_string()));
+            headers.push(("BlackHints".to_string(), self.hints[1].to_string()));
+        }
+        headers
+    }
+
+    /// Result of the main line, wherever the current move is.
+    fn main_line_result(&self) -> String {
+        let mut main_line = self.tree.record(self.tree.line_end(0));
+        main_line.variant_result = self.record.variant_result.clone();
+        main_line.result()
+    }
+
+    /// Plays `mv` as if the piece had been clicked and moved. Returns false if it was refused.
+    fn play_move(&mut self, mv: rules::Move) -> bool {
+        let plies = self.record.moves.len();
+        let promote_piece = self.promote_piece;
+        self.promote_piece = match mv.promotion {
+            rules::ROOK => 'r',
+            rules::BISHOP => 'b',
+            rules::KNIGHT => 'k',
+            _ => 'q',
+        };
+        self.current_piece = vec![self.board[mv.from as usize][0], self.board[mv.from as usize][1], mv.from];
+        self.move_piece(mv.to);
+        self.promote_piece = promote_piece;
+        self.current_piece = vec![0,0,64];
+        self.record.moves.len() > plies
+    }
+
+    /// Whether clicks on the board may move pieces.
+    fn accepting_moves(&self) -> bool {
+        if self.forfeit.is_some() || self.player_engines[AppState::colour_index(self.turn)].is_some() {
+            return false;
+        }
+        match &self.trainer {
+            Some(trainer) => trainer.state == PuzzleState::Solving && self.puzzle_reply.is_none(),
+            None => true,
+        }
+    }
+
+    /// Called after the user made a move on the board.
+    fn user_moved(&mut self) -> () {
+        let fen = self.record.current_fen().to_string();
+        if let Some(trainer) = self.trainer.as_mut() {
+            if let Some(reply) = trainer.check_move(&fen) {
+                self.puzzle_reply = Some((reply, time::Instant::now() + PUZZLE_REPLY_DELAY));
+            }
+        }
+    }
+
+    /// Turns the puzzle trainer on or off.
+    fn toggle_trainer(&mut self) -> () {
+        if self.trainer.is_some() {
+            self.trainer = None;
+            self.puzzle_reply = None;
+            return;
+        }
+        let file = env::var("PUZZLE_FILE").unwrap_or(DEFAULT_PUZZLE_FILE.to_string());
+        match puzzle::load(path::Path::new(&file)) {
+            Ok(puzzles) => {
+                println!("Loaded {} puzzles from {}", puzzles.len(), file);
+                self.trainer = Some(Trainer::new(puzzles));
+                self.start_puzzle();
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    /// Sets up the board for the trainer's current puzzle.
+    fn start_puzzle(&mut self) -> () {
+        let fen = match &self.trainer {
+            Some(trainer) => trainer.current().fen.clone(),
+            None => return,
+        };
+        self.puzzle_reply = None;
+        self.variant = Variant::Standard;
+        self.play_from(Position::from_fen(&fen).unwrap());
+    }
+
+    fn next_puzzle(&mut self) -> () {
+        if let Some(trainer) = self.trainer.as_mut() {
+            trainer.next();
+        }
+        self.start_puzzle();
+    }
+
+    /// Plays the trainer's pending reply once it is due.
+    fn play_puzzle_reply(&mut self) -> () {
+        if let Some((reply, due)) = self.puzzle_reply {
+            if time::Instant::now() >= due {
+                self.puzzle_reply = None;
+                self.play_move(reply);
+            }
+        }
+    }
+
+    /// Lines describing the active mode, shown above the promotion selector.
+    fn get_status_lines(&self) -> Vec<String> {
+        if let Some(replay) = &self.replay {
+            return replay.status_lines();
+        }
+        let mut lines = Vec::new();
+        if let Some(text) = &self.comment_entry {
+            lines.extend(AppState::wrap_text(&format!("Comment: {}_", text), 26));
+        }
+        if let Some(entry) = &self.move_entry {
+            lines.push(format!("Move: {}_", entry.text));
+            if entry.message.is_empty() {
+                lines.extend(AppState::wrap_text(&self.move_suggestions.join(" "), 26));
+            } else {
+                lines.extend(AppState::wrap_text(&entry.message, 26));
+            }
+        }
+        if let Some(trainer) = &self.trainer {
+            lines.extend(trainer.status_lines());
+        }
+        if let Some(tournament) = &self.tournament {
+            lines.extend(tournament.status_lines());
+        }
+        if let Some(clock) = &self.clock {
+            lines.push(clock.status_line());
+        }
+        if self.hint_engine.as_ref().map_or(false, Engine::is_searching) {
+            lines.push("Looking for a hint".to_string());
+        }
+        if let Some(variant_game) = &self.variant_game {
+            lines.extend(variant_game.status_lines());
+        }
+        if let Some(number) = self.chess960 {
+            lines.push(format!("Chess960 position {}", number));
+        }
+        if let Some(opening) = &self.opening {
+            lines.extend(AppState::wrap_text(&opening.to_text(), 26));
+        }
+        lines
+    }
+
+    /// Opens the board editor on the current position.
+    fn start_setup(&mut self) -> () {
+        let position = self.get_current_position();
+        self.setup = Some(Setup::new(position));
+        self.current_piece = vec![0,0,64];
+    }
+
+    /// Validates the edited position and starts play from it, or shows why it is invalid.
+    fn finish_setup(&mut self) -> () {
+        let result = match &self.setup {
+            Some(setup) => setup.validated(),
+            None => return,
+        };
+        match result {
+            Ok(position) => {
+                self.setup = None;
+                self.play_from(position);
+            }
+            Err(message) => {
+                println!("Invalid position: {}", message);
+                if let Some(setup) = self.setup.as_mut() {
+                    setup.message = message;
+                }
+            }
+        }
+    }
+
+    fn cancel_setup(&mut self) -> () {
+        self.setup = None;
+        let fen = self.get_current_fen();
+        self.update_board(fen);
+    }
+
+    fn refresh_setup_board(&mut self) -> () {
+        if let Some(setup) = &self.setup {
+            let fen = setup.position.to_fen();
+            self.update_board(fen);
+        }
+    }
+
+    /// Palette entry under the cursor as `(colour, piece)`, `(0, 0)` being the eraser.
+    fn get_palette_piece(x: f32, y: f32) -> Option<(u8, u8)> {
+        if x < PALETTE_ORIGIN.0 || y < PALETTE_ORIGIN.1 {
+            return None;
+        }
+        let col = ((x - PALETTE_ORIGIN.0) / PALETTE_TILE) as usize;
+        let row = ((y - PALETTE_ORIGIN.1) / PALETTE_TILE) as usize;
+        match row {
+            0 if col < 6 => Some((rules::WHITE, setup::PALETTE_PIECES[col])),
+            1 if col < 6 => Some((rules::BLACK, setup::PALETTE_PIECES[col])),
+            2 if col == 0 => Some((0, 0)),
+            _ => None,
+        }
+    }
+
+    fn setup_click(&mut self, pos: u8, x: f32, y: f32) -> () {
+        if let Some(setup) = self.setup.as_mut() {
+            if pos < 64 {
+                setup.place(pos);
+            } else if let Some(piece) = AppState::get_palette_piece(x, y) {
+                setup.selected = piece;
+            }
+        }
+        self.refresh_setup_board();
+    }
+
+    /// Draws left-aligned text in the information panel.
+    fn draw_panel_text(ctx: &mut Context, text: &str, y: f32, scale: f32) -> GameResult {
+        let panel_text = graphics::Text::new(
+            graphics::TextFragment::from(text)
+            .scale(graphics::PxScale { x: scale, y: scale }));
+        graphics::draw(ctx, &panel_text, graphics::DrawParam::default().color(REALBLACK)
+            .dest(ggez::mint::Point2 {
+                x: SCREEN_SIZE.0 + 25.0,
+                y: y,
+            }))
+    }
+
+    /// Draws the promotion piece selector.
+    fn draw_promotion_panel(&mut self, ctx: &mut Context) -> GameResult {
+
+        let mut promote_text = graphics::Text::new(
+            graphics::TextFragment::from(format!("Promotion piece:")
+            )
+            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+
+
+        let text_dimensions_promote = promote_text.dimensions(ctx);
 
         graphics::draw(ctx, &promote_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
             .dest(ggez::mint::Point2 {
@@ -541,8 +2069,8 @@ impl event::EventHandler<GameError> for AppState {
                     ((SCREEN_SIZE.1 as f32) / 2f32) as i32,
                     90 as i32,
                     90 as i32,
-                ), if self.promote_piece == 'q' { WHITE } 
-                else { BLACK },).expect("Failed to create tile.");
+                ), if self.promote_piece == 'q' { self.dark_colour() } 
+                else { self.light_colour() },).expect("Failed to create tile.");
         
         // draw Square
         graphics::draw(ctx, &queen_tile, graphics::DrawParam::default()).expect("Failed to draw tile.");    
@@ -554,8 +2082,8 @@ impl event::EventHandler<GameError> for AppState {
                 ((SCREEN_SIZE.1 as f32) / 2f32) as i32,
                 90 as i32,
                 90 as i32,
-            ), if self.promote_piece == 'r' { WHITE } 
-            else { BLACK },).expect("Failed to create tile.");
+            ), if self.promote_piece == 'r' { self.dark_colour() } 
+            else { self.light_colour() },).expect("Failed to create tile.");
     
         // draw Square
         graphics::draw(ctx, &rook_tile, graphics::DrawParam::default()).expect("Failed to draw tile.");   
@@ -567,8 +2095,8 @@ impl event::EventHandler<GameError> for AppState {
                 ((SCREEN_SIZE.1 as f32 +250f32) / 2f32) as i32,
                 90 as i32,
                 90 as i32,
-            ), if self.promote_piece == 'b' { WHITE } 
-            else { BLACK },).expect("Failed to create tile.");
+            ), if self.promote_piece == 'b' { self.dark_colour() } 
+            else { self.light_colour() },).expect("Failed to create tile.");
 
         // draw Square
         graphics::draw(ctx, &bishop_tile, graphics::DrawParam::default()).expect("Failed to draw tile.");   
@@ -580,8 +2108,8 @@ impl event::EventHandler<GameError> for AppState {
                 ((SCREEN_SIZE.1 as f32 + 250f32) / 2f32) as i32,
                 90 as i32,
                 90 as i32,
-            ), if self.promote_piece == 'k' { WHITE } 
-            else { BLACK },).expect("Failed to create tile.");
+            ), if self.promote_piece == 'k' { self.dark_colour() } 
+            else { self.light_colour() },).expect("Failed to create tile.");
 
         // draw Square
         graphics::draw(ctx, &knight_tile, graphics::DrawParam::default()).expect("Failed to draw tile.");   
@@ -618,6 +2146,315 @@ impl event::EventHandler<GameError> for AppState {
                 )
             ).expect("Failed to draw piece.");
 
+        Ok(())
+    }
+
+    /// Draws the piece palette and position settings of the board editor.
+    fn draw_setup_panel(&self, ctx: &mut Context) -> GameResult {
+        let setup = match &self.setup {
+            Some(setup) => setup,
+            None => return Ok(()),
+        };
+
+        AppState::draw_panel_text(ctx, "Setup:", PALETTE_ORIGIN.1 - 40.0, 30.0)?;
+
+        for (row, colour) in [rules::WHITE, rules::BLACK].iter().enumerate() {
+            for (col, piece) in setup::PALETTE_PIECES.iter().enumerate() {
+                let tile_x = PALETTE_ORIGIN.0 + col as f32 * PALETTE_TILE;
+                let tile_y = PALETTE_ORIGIN.1 + row as f32 * PALETTE_TILE;
+                let tile = graphics::Mesh::new_rectangle(ctx,
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new(tile_x, tile_y, PALETTE_TILE, PALETTE_TILE),
+                    if setup.selected == (*colour, *piece) { self.dark_colour() } else { self.light_colour() }).expect("Failed to create tile.");
+                graphics::draw(ctx, &tile, graphics::DrawParam::default()).expect("Failed to draw tile.");
+                graphics::draw(ctx, self.sprites.get(&(*colour, *piece)).unwrap(), graphics::DrawParam::default()
+                    .dest([tile_x, tile_y])
+                    ).expect("Failed to draw piece.");
+            }
+        }
+
+        // Eraser tile below the palette
+        let eraser_y = PALETTE_ORIGIN.1 + 2.0 * PALETTE_TILE;
+        let eraser = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(PALETTE_ORIGIN.0, eraser_y, PALETTE_TILE, PALETTE_TILE),
+            if setup.selected == (0, 0) { self.dark_colour() } else { self.light_colour() }).expect("Failed to create tile.");
+        graphics::draw(ctx, &eraser, graphics::DrawParam::default()).expect("Failed to draw tile.");
+        let eraser_text = graphics::Text::new(graphics::TextFragment::from("X").scale(graphics::PxScale { x: 30.0, y: 30.0 }));
+        graphics::draw(ctx, &eraser_text, graphics::DrawParam::default().color(REALBLACK)
+            .dest([PALETTE_ORIGIN.0 + 14.0, eraser_y + 8.0])).expect("Failed to draw text.");
+
+        let en_passant = if setup.picking_en_passant {
+            "click a square".to_string()
+        } else {
+            setup.position.en_passant.map_or("-".to_string(), rules::square_name)
+        };
+
+        let lines = [
+            format!("To move: {}", rules::colour_name(setup.position.turn)),
+            format!("Castling: {}", setup.position.castling_text()),
+            format!("En passant: {}", en_passant),
+            setup.message.clone(),
+            "C clear, S side to move".to_string(),
+            "1-4 castling KQkq".to_string(),
+            "X en passant, Back unset".to_string(),
+            "Enter play, Esc cancel".to_string(),
+        ];
+        let mut line_y = eraser_y + PALETTE_TILE + 10.0;
+        for line in lines.iter() {
+            AppState::draw_panel_text(ctx, line, line_y, 20.0)?;
+            line_y += PANEL_LINE_HEIGHT;
+        }
+
+        Ok(())
+    }
+
+}
+
+impl event::EventHandler<GameError> for AppState {
+
+    /// For updating game logic, which front-end doesn't handle.
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some(animation) = self.animation.as_mut() {
+            animation.advance(timer::delta(ctx));
+            if animation.finished() {
+                self.animation = None;
+            }
+        }
+        // The live game waits while a replay is shown on the board.
+        if let Some(replay) = self.replay.as_mut() {
+            if replay.advance(timer::delta(ctx)) {
+                let fen = replay.fen().to_string();
+                self.update_board(fen);
+            }
+            return Ok(());
+        }
+        self.poll_analysis();
+        self.poll_review();
+        self.poll_hint();
+        self.play_puzzle_reply();
+        self.check_clock();
+        self.advance_tournament();
+        self.play_engine_move();
+        self.play_premove();
+        Ok(())
+    }
+
+    /// Draw interface, i.e. draw game board
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+
+        // clear interface with gray background colour
+        graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
+
+        // create text representation
+        let state_text = graphics::Text::new(
+                graphics::TextFragment::from(format!("     Game is:\n    {}.", self.get_game_state_text())
+            )
+            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+
+        // get size of text
+        let text_dimensions = state_text.dimensions(ctx);
+        // create background rectangle with white coulouring
+        let background_box = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(),
+            graphics::Rect::new((SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 as f32 - 8.0,
+                                (SCREEN_SIZE.0 - text_dimensions.h as f32) / 2f32 as f32,
+                                text_dimensions.w as f32 + 16.0, text_dimensions.h as f32),
+                                [1.0, 1.0, 1.0, 1.0].into()
+        )?;
+
+        // draw background
+        graphics::draw(ctx, &background_box, graphics::DrawParam::default()).expect("Failed to draw background.");
+
+        // draw grid
+        for _row in 0..8 {
+            for _col in 0..8 {
+
+                // draw tile
+                let rectangle = graphics::Mesh::new_rectangle(ctx, 
+                    graphics::DrawMode::fill(), 
+                    graphics::Rect::new_i32(
+                        _col * GRID_CELL_SIZE.0 as i32,
+                        _row * GRID_CELL_SIZE.1 as i32,
+                        GRID_CELL_SIZE.0 as i32,
+                        GRID_CELL_SIZE.1 as i32,
+                    ), match _col % 2 {
+                        0 => 
+                            if _row % 2 == 0 { self.dark_colour() } 
+                            else { self.light_colour() },
+                        _ => 
+                            if _row % 2 == 0 { self.light_colour() } 
+                            else { self.dark_colour() },
+                    }).expect("Failed to create tile.");
+                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+
+            }
+        }
+
+        // Create line between info and board
+        let info_background = graphics::Mesh::new_rectangle(ctx, 
+            graphics::DrawMode::fill(), 
+            graphics::Rect::new_i32(
+                (GRID_CELL_SIZE.0 *8) as i32,
+                    0 as i32,
+                    300 as i32,
+                    (GRID_CELL_SIZE.0 *8) as i32,
+                    ), self.light_colour()).expect("Failed to create tile.");
+            
+        // draw line
+        graphics::draw(ctx, &info_background, graphics::DrawParam::default()).expect("Failed to draw background.");
+
+        // Create line between info and board
+        let board_line = graphics::Mesh::new_rectangle(ctx, 
+            graphics::DrawMode::fill(), 
+            graphics::Rect::new_i32(
+                (GRID_CELL_SIZE.0 *8) as i32,
+                0 as i32,
+                15 as i32,
+                (GRID_CELL_SIZE.0 *8) as i32,
+            ), {REALBLACK}).expect("Failed to create tile.");
+    
+        // draw line
+        graphics::draw(ctx, &board_line, graphics::DrawParam::default()).expect("Failed to draw background.");
+
+        if self.engine.is_some() {
+            self.draw_eval_bar(ctx)?;
+        }
+
+
+        // create text representation
+        let information_text = graphics::Text::new(
+            graphics::TextFragment::from(format!("Information: ")
+            )
+            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+
+        let text_dimensions_info = information_text.dimensions(ctx);
+
+        let mut turn_text = graphics::Text::new(
+            graphics::TextFragment::from(format!("Current player:\n     {}", self.current_turn)
+            )
+            .scale(graphics::PxScale     { x: 30.0, y: 30.0 }));
+
+
+        let text_dimensions_turn = turn_text.dimensions(ctx);
+
+
+
+        if let (Some((from, to)), None) = (self.premove, &self.replay) {
+            for sq in [from, to].iter() {
+                let screen = self.screen_square(*sq);
+                let premove_square = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(
+                        (screen % 8) as i32 * GRID_CELL_SIZE.0 as i32,
+                        (screen / 8) as i32 * GRID_CELL_SIZE.1 as i32,
+                        GRID_CELL_SIZE.0 as i32,
+                        GRID_CELL_SIZE.1 as i32,
+                    ), PREMOVE_COLOUR)?;
+                graphics::draw(ctx, &premove_square, graphics::DrawParam::default())?;
+            }
+        }
+
+        if let (Some((mv, show_target)), None) = (self.hint, &self.replay) {
+            let squares = if show_target { vec![mv.from, mv.to] } else { vec![mv.from] };
+            for sq in squares.iter() {
+                let screen = self.screen_square(*sq);
+                let hint_square = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(
+                        (screen % 8) as i32 * GRID_CELL_SIZE.0 as i32,
+                        (screen / 8) as i32 * GRID_CELL_SIZE.1 as i32,
+                        GRID_CELL_SIZE.0 as i32,
+                        GRID_CELL_SIZE.1 as i32,
+                    ), HINT_COLOUR)?;
+                graphics::draw(ctx, &hint_square, graphics::DrawParam::default())?;
+            }
+        }
+
+        for _rows in 0..8 {
+            for _file in 0..8 {
+            //draw piece
+            let arriving = self.animation.as_ref().map_or(false, |animation| animation.is_arriving((_rows*8 + _file) as u8));
+            if self.board[_rows*8 + _file as usize][0] != 0 && !arriving {
+                let screen = self.screen_square((_rows*8 + _file) as u8);
+                let (_col, _row) = ((screen % 8) as f32, (screen / 8) as f32);
+
+                if self.current_piece[2] == (_rows*8 + _file) as u8{
+                    graphics::draw(ctx, self.sprites.get(&(self.board[_rows*8 + _file as usize][0], self.board[_rows*8 + _file  as usize][1])).unwrap(), graphics::DrawParam::default()
+                    .scale([2.0, 2.0])  // Tile size is 90 pixels, while image sizes are 45 pixels.
+                    .dest(
+                        [_col * GRID_CELL_SIZE.0 as f32, _row * GRID_CELL_SIZE.1 as f32 - 12 as f32],
+                        )
+                    ).expect("Failed to draw piece.");
+                }else{
+                    graphics::draw(ctx, self.sprites.get(&(self.board[_rows*8 + _file as usize][0], self.board[_rows*8 + _file  as usize][1])).unwrap(), graphics::DrawParam::default()
+                    .scale([2.0, 2.0])  // Tile size is 90 pixels, while image sizes are 45 pixels.
+                    .dest(
+                        [_col * GRID_CELL_SIZE.0 as f32, _row * GRID_CELL_SIZE.1 as f32],
+                        )
+                    ).expect("Failed to draw piece.");
+                    }
+                }
+            }
+        }
+
+        self.draw_move_markers(ctx)?;
+
+        if self.animation.is_some() {
+            self.draw_animation(ctx)?;
+        }
+
+        if self.setup.is_none() && self.replay.is_none() {
+            self.draw_annotations(ctx)?;
+        }
+
+        // draw text with dark gray colouring and center position
+        graphics::draw(ctx, &state_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
+            .dest(ggez::mint::Point2 {
+                x: ((SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
+                y: (text_dimensions.h as f32+250.0) / 2f32 as f32,
+            })).expect("Failed to draw text.");
+    
+        // draw text with dark gray colouring and center position
+        graphics::draw(ctx, &information_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
+        .dest(ggez::mint::Point2 {
+            x: ((SCREEN_SIZE.0 - text_dimensions_info.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
+            y: (text_dimensions_info.h as f32+30.0) / 2f32 as f32,
+        })).expect("Failed to draw text.");
+
+        // draw text with dark gray colouring and center position
+        graphics::draw(ctx, &turn_text, graphics::DrawParam::default().color([0.0, 0.0, 0.0, 1.0].into())
+            .dest(ggez::mint::Point2 {
+                x: ((SCREEN_SIZE.0 - text_dimensions_turn.w as f32) / 2f32 + (SCREEN_SIZE.0 +300.0)/2f32  ) as f32,
+                y: (text_dimensions_turn.h as f32+90.0) / 2f32 as f32,
+            })).expect("Failed to draw text.");
+
+        let mut line_y = STATUS_LINES_Y;
+        for line in self.get_status_lines().iter().take(4) {
+            AppState::draw_panel_text(ctx, line, line_y, 20.0)?;
+            line_y += PANEL_LINE_HEIGHT;
+        }
+
+        if self.setup.is_some() {
+            self.draw_setup_panel(ctx)?;
+        } else {
+            if self.replay.is_some() {
+                self.draw_replay_panel(ctx)?;
+            } else if self.review.is_some() || self.report.is_some() {
+                self.draw_report_panel(ctx)?;
+            } else if self.explorer.is_some() {
+                self.draw_explorer_panel(ctx)?;
+            } else if self.show_moves {
+                self.draw_moves_panel(ctx)?;
+            } else {
+                self.draw_promotion_panel(ctx)?;
+            }
+            let mut line_y = SCREEN_SIZE.1 - 140.0;
+            for line in self.analysis_lines.iter().take(4) {
+                AppState::draw_panel_text(ctx, line, line_y, 20.0)?;
+                line_y += PANEL_LINE_HEIGHT;
+            }
+            AppState::draw_panel_text(ctx, "E edit, A analysis, B best move", SCREEN_SIZE.1 - 46.0, 20.0)?;
+            AppState::draw_panel_text(ctx, "P PGN, T puzzles, X explorer", SCREEN_SIZE.1 - 24.0, 20.0)?;
+        }
+
         // render updated graphics
         graphics::present(ctx).expect("Failed to update graphics.");
 
@@ -627,26 +2464,174 @@ impl event::EventHandler<GameError> for AppState {
 
     /// Update game on mouse click
     fn mouse_button_up_event(&mut self, ctx: &mut Context, button: event::MouseButton, x: f32, y: f32) {
+        let (x, y) = self.to_layout(x, y);
         if button == event::MouseButton::Left {
             let pos = self.get_square(x,y);
-            if pos == 90 {
+            if self.setup.is_some() {
+                self.setup_click(pos, x, y);
+            }else if self.replay.is_some() {
+                self.replay_click(y);
+            }else if pos == 90 && (self.review.is_some() || self.report.is_some()) {
+                // The report covers the other panels and has nothing to click.
+            }else if pos == 90 && self.explorer.is_some() {
+                self.explorer_click(y);
+            }else if pos == 90 && self.show_moves {
+                // The moves panel covers the promotion selector and has nothing to click.
+            }else if pos == 90 {
                 self.get_promote_piece(x,y);
+                let promotion = if self.promote_piece == 'k' { 'n' } else { self.promote_piece };
+                if promotion != self.settings.promotion {
+                    self.settings.promotion = promotion;
+                    self.save_settings();
+                }
+            }else if let Some(colour) = self.premove_colour() {
+                self.premove_click(colour, pos);
             }else{
                 //println!("Clicked piece is: {:#?}",self.board[pos as usize]);
-                if self.turn == self.board[pos as usize][0] {
+                // In Chess960 the king castles by moving onto its own rook.
+                let castles = self.current_piece[1] == rules::KING && self.current_piece[2] < 64
+                    && self.get_possible_moves(self.current_piece[2]).contains(&AppState::u8_to_str(pos));
+                if self.turn == self.board[pos as usize][0] && !castles {
                     //println!("Changing selected piece");
                     self.current_piece[0] = self.board[pos as usize][0];
                     self.current_piece[1] = self.board[pos as usize][1];
                     self.current_piece[2] = pos;
                 
                 }else{
-                    if self.current_piece[0] != 0 {
+                    if self.current_piece[0] != 0 && self.accepting_moves() {
+                        let plies = self.record.moves.len();
                         self.move_piece(pos);
+                        if self.record.moves.len() > plies {
+                            self.user_moved();
+                        }
                     }
                 }
             }
+        } else if button == event::MouseButton::Right && self.setup.is_none() && self.replay.is_none() {
+            let pos = self.get_square(x,y);
+            self.annotate(ctx, pos);
+        }
+    }
+
+    /// Starts drawing an arrow or circle on right click.
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: event::MouseButton, x: f32, y: f32) {
+        let (x, y) = self.to_layout(x, y);
+        if button == event::MouseButton::Right && self.setup.is_none() {
+            let pos = self.get_square(x,y);
+            self.arrow_start = if pos < 64 { Some(pos) } else { None };
+        }
+    }
+
+    /// Typed characters for the move or comment entry.
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let Some(text) = self.comment_entry.as_mut() {
+            // Braces would end the PGN comment early.
+            if !character.is_control() && character != '{' && character != '}' {
+                text.push(character);
+            }
+        } else if let Some(entry) = self.move_entry.as_mut() {
+            entry.push(character);
+            self.refresh_move_suggestions();
+        }
+    }
+
+    /// Keeps the layout filling the window and remembers the size.
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.window_size = (width, height);
+        self.settings.window = (width, height);
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, WINDOW_SIZE.0, WINDOW_SIZE.1))
+            .expect("Failed to set screen coordinates.");
+    }
+
+    /// Saves the settings, including the window size, when the window is closed.
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.save_settings();
+        false
+    }
+
+    /// Keyboard shortcuts, mostly for the board editor.
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods, _repeat: bool) {
+        if self.move_entry.is_some() {
+            self.move_entry_key(keycode);
+            return;
+        }
+        if self.comment_entry.is_some() {
+            self.comment_entry_key(keycode);
+            return;
+        }
+        if self.replay.is_some() {
+            self.replay_key(keycode);
+            return;
+        }
+        if self.setup.is_none() {
+            match keycode {
+                KeyCode::Return if keymods.contains(KeyMods::SHIFT) => {
+                    self.comment_entry = Some(self.tree.nodes[self.tree.current].comment.clone());
+                }
+                KeyCode::Return => {
+                    self.move_entry = Some(MoveEntry::new());
+                    self.refresh_move_suggestions();
+                }
+                KeyCode::E => self.start_setup(),
+                KeyCode::A => self.toggle_analysis(),
+                KeyCode::B => self.show_best_move = !self.show_best_move,
+                KeyCode::P => self.export_pgn(),
+                KeyCode::T => self.toggle_trainer(),
+                KeyCode::N => self.next_puzzle(),
+                KeyCode::X => self.toggle_explorer(),
+                KeyCode::V => self.next_variant(),
+                KeyCode::C => self.next_theme(ctx),
+                KeyCode::F => self.flip_board(),
+                KeyCode::M => self.toggle_mute(),
+                KeyCode::R => self.toggle_replay(),
+                KeyCode::L => self.show_moves = !self.show_moves,
+                KeyCode::G => self.toggle_report(),
+                KeyCode::H => self.request_hint(),
+                KeyCode::Left => self.navigate(self.tree.back()),
+                KeyCode::Right => self.navigate(self.tree.forward()),
+                KeyCode::Up => self.navigate(self.tree.sibling(-1)),
+                KeyCode::Down => self.navigate(self.tree.sibling(1)),
+                KeyCode::Home => self.navigate(Some(0)),
+                KeyCode::End => self.navigate(Some(self.tree.line_end(self.tree.current))),
+                KeyCode::PageUp => {
+                    self.tree.promote();
+                }
+                KeyCode::Delete => self.delete_variation(),
+                KeyCode::Key1 => self.tree.toggle_nag(1),
+                KeyCode::Key2 => self.tree.toggle_nag(2),
+                KeyCode::Key3 => self.tree.toggle_nag(3),
+                KeyCode::Key4 => self.tree.toggle_nag(4),
+                KeyCode::Key5 => self.tree.toggle_nag(5),
+                KeyCode::Key6 => self.tree.toggle_nag(6),
+                KeyCode::Key9 => self.start_chess960(env::var("CHESS960_POSITION").ok().and_then(|number| number.parse().ok())),
+                KeyCode::Escape => {
+                    self.save_settings();
+                    event::quit(ctx);
+                }
+                _ => {}
+            }
+            return;
+        }
 
-            self.update(ctx);
+        match keycode {
+            KeyCode::Return => self.finish_setup(),
+            KeyCode::Escape => self.cancel_setup(),
+            _ => {
+                if let Some(setup) = self.setup.as_mut() {
+                    match keycode {
+                        KeyCode::C => setup.clear(),
+                        KeyCode::S => setup.toggle_turn(),
+                        KeyCode::Key1 => setup.toggle_castling(rules::WHITE_KING_SIDE),
+                        KeyCode::Key2 => setup.toggle_castling(rules::WHITE_QUEEN_SIDE),
+                        KeyCode::Key3 => setup.toggle_castling(rules::BLACK_KING_SIDE),
+                        KeyCode::Key4 => setup.toggle_castling(rules::BLACK_QUEEN_SIDE),
+                        KeyCode::X => setup.picking_en_passant = !setup.picking_en_passant,
+                        KeyCode::Back => setup.position.en_passant = None,
+                        _ => {}
+                    }
+                }
+                self.refresh_setup_board();
+            }
         }
     }
 }
@@ -655,6 +2640,48 @@ impl event::EventHandler<GameError> for AppState {
 
 pub fn main() -> GameResult {
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match cli::parse(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("{}", error);
+            return Ok(());
+        }
+    };
+    if options.command == Command::Help {
+        println!("{}", cli::HELP);
+        return Ok(());
+    }
+    if options.command == Command::Perft {
+        if let Err(error) = perft::run_command(&options) {
+            println!("{}", error);
+        }
+        return Ok(());
+    }
+    if let Command::Report(file) = &options.command {
+        if let Err(error) = AppState::run_report(path::Path::new(file), &options) {
+            println!("{}", error);
+        }
+        return Ok(());
+    }
+    if let (Command::Match(first, second), true) = (&options.command, options.headless) {
+        let result = AppState::create_tournament(first, second, &options).and_then(|mut tournament| tournament.run());
+        if let Err(error) = result {
+            println!("{}", error);
+        }
+        return Ok(());
+    }
+
+    let settings = Settings::load(WINDOW_SIZE);
+    let mut launch = settings.clone();
+    launch.flipped = options.flip.unwrap_or(launch.flipped);
+    for (key, value) in options.settings.iter() {
+        if let Err(error) = launch.set(key, value) {
+            println!("--{}: {}", key, error);
+            return Ok(());
+        }
+    }
+
     let resource_dir = path::PathBuf::from("./resources");
 
     let context_builder = ContextBuilder::new("schack", "Oskar")
@@ -666,13 +2693,17 @@ pub fn main() -> GameResult {
         )
         .window_mode(
             conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0+300.0, SCREEN_SIZE.1) // Set window dimensions
-                .resizable(false)               // Fixate window size
-        ).modules(conf::ModuleConf::default().audio(false));
+                .dimensions(launch.window.0, launch.window.1) // Set window dimensions
+                .resizable(true)
+        ).modules(conf::ModuleConf::default().audio(cfg!(feature = "audio")));
     let (mut contex, mut event_loop) = context_builder.build().expect("Failed to build context.");
     
 
-    let mut state = AppState::new(&mut contex).expect("Failed to create state.");
+    let mut state = AppState::new(&mut contex, settings, &launch).expect("Failed to create state.");
     state.create_board();
+    if let Err(error) = state.apply_options(&options) {
+        println!("{}", error);
+        return Ok(());
+    }
     event::run(contex, event_loop, state)       // Run window event loop
 }
\ No newline at end of file
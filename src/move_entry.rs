@@ -0,0 +1,86 @@
+//! Typed move entry in SAN (`Nf3`, `O-O`, `e8=Q`) or UCI (`e2e4`, `e7e8q`),
+//! resolved against the legal moves with prefix autocompletion.
+
+use crate::rules::Move;
+
+/// Completions listed in the side panel.
+pub const MAX_SUGGESTIONS: usize = 6;
+
+#[derive(Default)]
+pub struct MoveEntry {
+    pub text: String,
+    /// Why the last entry was refused.
+    pub message: String,
+}
+
+/// SAN without check marks or annotations, with castling written with letters.
+fn normalise(san: &str) -> String {
+    let san = if san.starts_with('0') { san.replace('0', "O") } else { san.to_string() };
+    san.chars().filter(|c| !"+#!?".contains(*c)).collect()
+}
+
+impl MoveEntry {
+    pub fn new() -> MoveEntry {
+        MoveEntry::default()
+    }
+
+    pub fn push(&mut self, c: char) {
+        if !c.is_control() && !c.is_whitespace() {
+            self.text.push(c);
+            self.message.clear();
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+        self.message.clear();
+    }
+
+    /// Legal moves, given with their SAN, whose SAN or UCI starts with the text typed so far.
+    pub fn completions<'a>(&self, moves: &'a [(String, Move)]) -> Vec<&'a (String, Move)> {
+        let typed = normalise(&self.text);
+        moves.iter()
+            .filter(|(san, mv)| normalise(san).starts_with(&typed) || mv.to_uci().starts_with(&self.text.to_lowercase()))
+            .collect()
+    }
+
+    /// The move the text names: an exact SAN or UCI match, or the only completion.
+    pub fn resolve(&self, moves: &[(String, Move)]) -> Result<Move, String> {
+        let typed = normalise(&self.text);
+        // "=" is optional in promotions, so "e8Q" works as well as "e8=Q".
+        let exact = moves.iter().find(|(san, mv)| {
+            normalise(san) == typed || normalise(san).replace('=', "") == typed || mv.to_uci() == self.text.to_lowercase()
+        });
+        if let Some((_, mv)) = exact {
+            return Ok(*mv);
+        }
+        let completions = self.completions(moves);
+        match completions.len() {
+            1 => Ok(completions[0].1),
+            0 => Err(format!("No legal move {}", self.text)),
+            _ => Err(format!("Ambiguous: {}", self.suggestions(moves).join(" "))),
+        }
+    }
+
+    /// Extends the text to the longest SAN prefix shared by all completions.
+    pub fn complete(&mut self, moves: &[(String, Move)]) {
+        let completions = self.completions(moves);
+        let mut sans = completions.iter().map(|(san, _)| normalise(san));
+        let mut common = match sans.next() {
+            Some(first) => first,
+            None => return,
+        };
+        for san in sans {
+            let shared = common.chars().zip(san.chars()).take_while(|(a, b)| a == b).count();
+            common.truncate(common.char_indices().nth(shared).map_or(common.len(), |(index, _)| index));
+        }
+        if common.len() > self.text.len() && common.starts_with(&normalise(&self.text)) {
+            self.text = common;
+        }
+    }
+
+    /// SAN of the first few completions.
+    pub fn suggestions(&self, moves: &[(String, Move)]) -> Vec<String> {
+        self.completions(moves).iter().take(MAX_SUGGESTIONS).map(|(san, _)| san.clone()).collect()
+    }
+}
@@ -0,0 +1,124 @@
+//! Move path enumeration ("perft") to check the move generators against the
+//! published node counts of the standard test positions.
+
+use std::time::Instant;
+
+use crate::bitboard;
+use crate::cli::Options;
+use crate::rules::{Move, Position};
+
+/// Depth of the `perft` command without `--depth`.
+pub const DEFAULT_DEPTH: u32 = 4;
+
+/// Position name, FEN and node counts from depth 1, as listed on the Chess Programming Wiki.
+pub const SUITE: [(&str, &str, &[u64]); 6] = [
+    ("Initial position", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4865609]),
+    ("Kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862, 4085603]),
+    ("Position 3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238, 674624]),
+    ("Position 4", "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467, 422333]),
+    ("Position 5", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379, 2103487]),
+    ("Position 6", "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890, 3894594]),
+];
+
+/// Number of move paths of `depth` plies from `position`, using `legal_moves` to generate moves.
+pub fn perft(position: &Position, depth: u32, legal_moves: fn(&Position) -> Vec<Move>) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = legal_moves(position);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves.iter().map(|mv| {
+        let mut next = position.clone();
+        next.make_move(*mv);
+        perft(&next, depth - 1, legal_moves)
+    }).sum()
+}
+
+/// Node count below each move, as UCI move and count.
+pub fn divide(position: &Position, depth: u32) -> Vec<(String, u64)> {
+    bitboard::legal_moves(position).iter().map(|mv| {
+        let mut next = position.clone();
+        next.make_move(*mv);
+        (mv.to_uci(), perft(&next, depth.saturating_sub(1), bitboard::legal_moves))
+    }).collect()
+}
+
+/// Runs the suite up to `max_depth` with the bitboard generator, printing each count.
+/// Returns false if any count is wrong.
+pub fn run_suite(max_depth: u32) -> bool {
+    let mut passed = true;
+    for (name, fen, counts) in SUITE.iter() {
+        let position = Position::from_fen(fen).unwrap();
+        for (depth, expected) in (1..).zip(counts.iter()).take_while(|(depth, _)| *depth <= max_depth) {
+            let start = Instant::now();
+            let nodes = perft(&position, depth, bitboard::legal_moves);
+            let ok = nodes == *expected;
+            passed &= ok;
+            println!("{:<16} depth {}  {:>9} nodes  {:>6.2}s  {}", name, depth, nodes, start.elapsed().as_secs_f64(),
+                if ok { "ok".to_string() } else { format!("FAILED, expected {}", expected) });
+        }
+    }
+    passed
+}
+
+/// Compares the bitboard generator with `Position::legal_moves` over every position up to
+/// `depth` plies from `position`, printing the FEN of the first disagreement.
+pub fn cross_check(position: &Position, depth: u32) -> bool {
+    let mut expected = position.legal_moves();
+    let mut moves = bitboard::legal_moves(position);
+    let key = |mv: &Move| (mv.from, mv.to, mv.promotion);
+    expected.sort_by_key(key);
+    moves.sort_by_key(key);
+    if moves != expected {
+        println!("Move generators disagree in {}", position.to_fen());
+        return false;
+    }
+    depth == 0 || moves.iter().all(|mv| {
+        let mut next = position.clone();
+        next.make_move(*mv);
+        cross_check(&next, depth - 1)
+    })
+}
+
+/// The `perft` command with the `--fen`, `--depth` and `--compare` options. It needs no window,
+/// so both the GUI binary and the `perft` binary run it.
+pub fn run_command(options: &Options) -> Result<(), String> {
+    run(options.fen.as_deref(), options.depth.unwrap_or(DEFAULT_DEPTH), options.compare)
+}
+
+/// Divide for `fen`, or the whole suite without one. With `compare` the bitboard generator is
+/// also checked against `Position::legal_moves` down to `depth`.
+pub fn run(fen: Option<&str>, depth: u32, compare: bool) -> Result<(), String> {
+    let positions = match fen {
+        Some(fen) => {
+            let position = Position::from_fen(fen)?;
+            let counts = divide(&position, depth);
+            for (mv, nodes) in counts.iter() {
+                println!("{}: {}", mv, nodes);
+            }
+            println!("Nodes: {}", counts.iter().map(|(_, nodes)| nodes).sum::<u64>());
+            vec![position]
+        }
+        None => {
+            if !run_suite(depth) {
+                return Err("Perft counts differ from the suite".to_string());
+            }
+            SUITE.iter().map(|(_, fen, _)| Position::from_fen(fen).unwrap()).collect()
+        }
+    };
+    if compare {
+        if !positions.iter().all(|position| cross_check(position, depth.saturating_sub(1))) {
+            return Err("The bitboard and rules move generators disagree".to_string());
+        }
+        println!("The bitboard and rules move generators agree");
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! Record of the moves played in the current game, PGN export and PGN reading.
+
+use crate::rules::{self, Position, Status};
+
+pub struct GameRecord {
+    /// FEN before each move, followed by the FEN after the last move.
+    pub fens: Vec<String>,
+    /// Moves in SAN.
+    pub moves: Vec<String>,
+    /// Result the final position alone does not show, e.g. a variant win, a loss on time or an adjudicated draw.
+    pub variant_result: Option<String>,
+}
+
+impl GameRecord {
+    pub fn new(start_fen: String) -> GameRecord {
+        GameRecord {
+            fens: vec![start_fen],
+            moves: Vec::new(),
+            variant_result: None,
+        }
+    }
+
+    pub fn push(&mut self, san: String, fen_after: String) {
+        self.moves.push(san);
+        self.fens.push(fen_after);
+    }
+
+    pub fn start_fen(&self) -> &str {
+        &self.fens[0]
+    }
+
+    pub fn current_fen(&self) -> &str {
+        self.fens.last().unwrap()
+    }
+
+    /// PGN result token from the final position.
+    pub fn result(&self) -> String {
+        if let Some(result) = &self.variant_result {
+            return result.clone();
+        }
+        let position = Position::from_fen(self.current_fen()).unwrap_or(Position::start());
+        match position.status() {
+            Status::Checkmate if position.turn == rules::WHITE => "0-1".to_string(),
+            Status::Checkmate => "1-0".to_string(),
+            Status::Stalemate => "1/2-1/2".to_string(),
+            _ => "*".to_string(),
+        }
+    }
+
+    /// Writes the game as PGN. `comments[i]` is placed after ply `i`, `comments[0]` before the first move.
+    pub fn to_pgn(&self, headers: &[(String, String)], comments: &[String]) -> String {
+        let mut words: Vec<String> = Vec::new();
+        let comment_at = |ply: usize| comments.get(ply).filter(|comment| !comment.is_empty());
+        if let Some(comment) = comment_at(0) {
+            words.push(format!("{{{}}}", comment));
+        }
+        for (i, san) in self.moves.iter().enumerate() {
+            let position = Position::from_fen(&self.fens[i]).unwrap_or(Position::start());
+            if position.turn == rules::WHITE {
+                words.push(format!("{}.", position.fullmove));
+            } else if i == 0 || comment_at(i).is_some() {
+                words.push(format!("{}...", position.fullmove));
+            }
+            words.push(san.clone());
+            if let Some(comment) = comment_at(i + 1) {
+                words.push(format!("{{{}}}", comment));
+            }
+        }
+        write_pgn(headers, self.start_fen(), &self.result(), words)
+    }
+}
+
+/// PGN text from headers and movetext words. SetUp and FEN headers are added for a
+/// non-standard start, and the result is written as a header and after the moves.
+pub fn write_pgn(headers: &[(String, String)], start_fen: &str, result: &str, mut words: Vec<String>) -> String {
+    let mut pgn = String::new();
+    for (name, value) in headers.iter() {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_header(value)));
+    }
+    if start_fen != rules::START_FEN {
+        pgn.push_str("[SetUp \"1\"]\n");
+        pgn.push_str(&format!("[FEN \"{}\"]\n", start_fen));
+    }
+    pgn.push_str(&format!("[Result \"{}\"]\n\n", result));
+    words.push(result.to_string());
+
+    // Keep lines under 80 characters as the export format asks.
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + word.len() + 1 > 79 {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
+
+/// A movetext token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Move(String),
+    Comment(String),
+    /// Numeric annotation glyph, `$1` or a suffix like `!`.
+    Nag(u8),
+    /// Start of a variation, `(`.
+    Open,
+    /// End of a variation, `)`.
+    Close,
+    Result(String),
+}
+
+/// A game read from a PGN file.
+pub struct PgnGame {
+    pub headers: Vec<(String, String)>,
+    pub tokens: Vec<Token>,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Position the game starts from, honouring the `FEN` header.
+    pub fn start_fen(&self) -> String {
+        self.header("FEN").unwrap_or(rules::START_FEN).to_string()
+    }
+
+    /// Main line moves in SAN, without variations.
+    pub fn main_line(&self) -> Vec<String> {
+        let mut depth = 0;
+        let mut moves = Vec::new();
+        for token in self.tokens.iter() {
+            match token {
+                Token::Open => depth += 1,
+                Token::Close => depth -= 1,
+                Token::Move(san) if depth == 0 => moves.push(san.clone()),
+                _ => {}
+            }
+        }
+        moves
+    }
+}
+
+/// NAG number of a move suffix such as "!?".
+pub fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+/// Splits movetext into tokens, dropping move numbers and escape lines.
+pub fn tokenize(movetext: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut number = String::new();
+                while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    number.push(*digit);
+                    chars.next();
+                }
+                if let Ok(nag) = number.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.peek().filter(|c| !c.is_whitespace() && !"{}();$".contains(**c)) {
+                    word.push(*next);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        tokens.push(Token::Result(word));
+                        continue;
+                    }
+                    _ => {}
+                }
+                // Strip a leading move number such as "12." or "12...", leaving "0-0" alone.
+                let number = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                let word = if number.starts_with('.') { number.trim_start_matches('.') } else { word.as_str() };
+                if word.is_empty() {
+                    continue;
+                }
+                if let Some(nag) = suffix_nag(word) {
+                    tokens.push(Token::Nag(nag));
+                    continue;
+                }
+                let san = word.trim_end_matches(['!', '?']);
+                tokens.push(Token::Move(san.to_string()));
+                if let Some(nag) = suffix_nag(&word[san.len()..]) {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// Header value with `\` and `"` escaped, as `parse_header` reads it back.
+fn escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A `[Name "value"]` line, with the value unescaped.
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_at(inner.find(' ')?);
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let quoted = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        value.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+    }
+    Some((name.to_string(), value))
+}
+
+/// Whether `movetext` ends inside a `{` comment.
+fn in_comment(movetext: &str) -> bool {
+    movetext.rfind('{') > movetext.rfind('}')
+}
+
+/// Reads every game in a PGN file.
+pub fn parse_games(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut headers = Vec::new();
+    let mut movetext = String::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        // Comments may wrap onto lines such as "[%clk 0:03:00] }", which are not headers.
+        let comment = in_comment(&movetext);
+        match parse_header(trimmed).filter(|_| !comment) {
+            Some(header) => {
+                if !movetext.trim().is_empty() {
+                    games.push(PgnGame { headers: headers.split_off(0), tokens: tokenize(&movetext) });
+                    movetext.clear();
+                }
+                headers.push(header);
+            }
+            None if !comment && trimmed.starts_with('%') => continue,
+            None if !comment && trimmed.starts_with('[') && movetext.trim().is_empty() => continue,
+            None => {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+    }
+    if !headers.is_empty() || !movetext.trim().is_empty() {
+        games.push(PgnGame { headers, tokens: tokenize(&movetext) });
+    }
+    games
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapped_comment_lines_stay_in_the_game() {
+        let text = "[Event \"Casual\"]\n\n1. e4 { good\n[%clk 0:03:00] } e5 2. Nf3 {\n[%cal Ge2e4] } Nc6 1-0\n";
+        let games = parse_games(text);
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].main_line(), vec!["e4", "e5", "Nf3", "Nc6"]);
+    }
+
+    #[test]
+    fn headers_after_movetext_start_a_new_game() {
+        let text = "[White \"A\"]\n\n1. e4 e5 1-0\n\n[White \"B\"]\n\n1. d4 0-1\n";
+        let games = parse_games(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].header("White"), Some("A"));
+        assert_eq!(games[1].header("White"), Some("B"));
+        assert_eq!(games[1].main_line(), vec!["d4"]);
+    }
+
+    #[test]
+    fn escaped_header_values_survive_a_round_trip() {
+        let headers = vec![("Event".to_string(), "The \"Big\" C:\\games".to_string())];
+        let pgn = write_pgn(&headers, rules::START_FEN, "*", vec!["1.".to_string(), "e4".to_string()]);
+        assert!(pgn.starts_with("[Event \"The \\\"Big\\\" C:\\\\games\"]\n"));
+        let games = parse_games(&pgn);
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].header("Event"), Some("The \"Big\" C:\\games"));
+        assert_eq!(games[0].main_line(), vec!["e4"]);
+    }
+}
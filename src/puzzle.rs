@@ -0,0 +1,275 @@
+//! Tactics trainer: puzzle sets loaded from CSV or EPD files and the
+//! bookkeeping of a solving session.
+//!
+//! CSV lines are `fen,moves` with the solution as space separated UCI or SAN
+//! moves, starting with the solver's move. Files with the Lichess puzzle
+//! header (`PuzzleId,FEN,Moves,...`) are also read; there the first move is
+//! the opponent's and is played before the puzzle starts.
+//!
+//! EPD lines are four FEN fields followed by operations, of which `pv`
+//! (the full solution) or `bm` (a single best move) is used, and `id` names
+//! the puzzle.
+
+use std::fs;
+use std::path::Path;
+
+use crate::rules::{self, Move, Position};
+
+pub struct Puzzle {
+    pub id: String,
+    /// Position the solver moves from.
+    pub fen: String,
+    /// Alternating solver and opponent moves.
+    pub solution: Vec<Move>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleState {
+    Solving,
+    Solved,
+    Failed,
+}
+
+pub struct Trainer {
+    pub puzzles: Vec<Puzzle>,
+    pub index: usize,
+    /// Index into the current solution of the next move to be played.
+    pub ply: usize,
+    pub state: PuzzleState,
+    pub solved: u32,
+    pub failed: u32,
+    pub streak: u32,
+    pub message: String,
+}
+
+/// Plays `moves` from `fen`, returning the start FEN and the resolved moves.
+/// With `skip_first` the first move is played and not part of the solution.
+fn build_puzzle(id: String, fen: &str, moves: &[&str], skip_first: bool) -> Result<Puzzle, String> {
+    let mut position = Position::from_fen(fen)?;
+    let mut start_fen = position.to_fen();
+    let mut solution = Vec::new();
+    for (i, text) in moves.iter().enumerate() {
+        let mv = position.parse_move(text).ok_or(format!("Illegal move {} in puzzle {}", text, id))?;
+        position.make_move(mv);
+        if skip_first && i == 0 {
+            start_fen = position.to_fen();
+        } else {
+            solution.push(mv);
+        }
+    }
+    if solution.is_empty() {
+        return Err(format!("Puzzle {} has no solution", id));
+    }
+    Ok(Puzzle { id, fen: start_fen, solution })
+}
+
+fn parse_csv(text: &str) -> Vec<Result<Puzzle, String>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty()).peekable();
+    let lichess = lines.peek().is_some_and(|header| header.starts_with("PuzzleId"));
+    if lichess {
+        lines.next();
+    }
+
+    lines.enumerate().map(|(i, line)| {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if lichess {
+            if fields.len() < 3 {
+                return Err(format!("Line {} is missing fields", i + 2));
+            }
+            let moves: Vec<&str> = fields[2].split_whitespace().collect();
+            build_puzzle(fields[0].to_string(), fields[1], &moves, true)
+        } else {
+            if fields.len() < 2 {
+                return Err(format!("Line {} is missing fields", i + 1));
+            }
+            let moves: Vec<&str> = fields[1].split_whitespace().collect();
+            build_puzzle(format!("{}", i + 1), fields[0], &moves, false)
+        }
+    }).collect()
+}
+
+fn parse_epd(text: &str) -> Vec<Result<Puzzle, String>> {
+    text.lines().filter(|line| !line.trim().is_empty()).enumerate().map(|(i, line)| {
+        let fields: Vec<&str> = line.splitn(5, ' ').collect();
+        if fields.len() < 5 {
+            return Err(format!("Line {} is missing fields", i + 1));
+        }
+        let fen = format!("{} 0 1", fields[..4].join(" "));
+        let mut id = format!("{}", i + 1);
+        let mut moves: Vec<&str> = Vec::new();
+        for operation in fields[4].split(';') {
+            let mut words = operation.split_whitespace();
+            match words.next() {
+                Some("id") => id = words.collect::<Vec<&str>>().join(" ").trim_matches('"').to_string(),
+                Some("pv") => moves = words.collect(),
+                Some("bm") if moves.is_empty() => moves = words.take(1).collect(),
+                _ => {}
+            }
+        }
+        build_puzzle(id, &fen, &moves, false)
+    }).collect()
+}
+
+/// Loads a puzzle set, skipping (and reporting) puzzles that cannot be played.
+pub fn load(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let results = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("epd") => parse_epd(&text),
+        _ => parse_csv(&text),
+    };
+
+    let mut puzzles = Vec::new();
+    for result in results {
+        match result {
+            Ok(puzzle) => puzzles.push(puzzle),
+            Err(error) => println!("Skipping puzzle: {}", error),
+        }
+    }
+    if puzzles.is_empty() {
+        return Err(format!("No playable puzzles in {}", path.display()));
+    }
+    Ok(puzzles)
+}
+
+impl Trainer {
+    pub fn new(puzzles: Vec<Puzzle>) -> Trainer {
+        Trainer {
+            puzzles,
+            index: 0,
+            ply: 0,
+            state: PuzzleState::Solving,
+            solved: 0,
+            failed: 0,
+            streak: 0,
+            message: String::new(),
+        }
+    }
+
+    pub fn current(&self) -> &Puzzle {
+        &self.puzzles[self.index]
+    }
+
+    /// Moves on to the next puzzle, wrapping around at the end of the set.
+    pub fn next(&mut self) {
+        self.index = (self.index + 1) % self.puzzles.len();
+        self.ply = 0;
+        self.state = PuzzleState::Solving;
+        self.message.clear();
+    }
+
+    /// Position before solution move `ply`.
+    fn position_at(&self, ply: usize) -> Position {
+        let mut position = Position::from_fen(&self.current().fen).unwrap();
+        for mv in self.current().solution[..ply].iter() {
+            position.make_move(*mv);
+        }
+        position
+    }
+
+    /// Checks the solver's move, given the FEN after it. Returns the opponent's reply to play, if any.
+    /// On the last move any checkmate is accepted.
+    pub fn check_move(&mut self, fen_after: &str) -> Option<Move> {
+        if self.state != PuzzleState::Solving {
+            return None;
+        }
+        let before = self.position_at(self.ply);
+        let expected = self.current().solution[self.ply];
+        let mut after = before.clone();
+        after.make_move(expected);
+
+        let last = self.ply + 1 == self.current().solution.len();
+        let played = Position::from_fen(fen_after).ok();
+        let mates = last && played.as_ref().is_some_and(|position| position.status() == rules::Status::Checkmate);
+        if rules::position_key(fen_after) != rules::position_key(&after.to_fen()) && !mates {
+            self.state = PuzzleState::Failed;
+            self.failed += 1;
+            self.streak = 0;
+            self.message = format!("Wrong, {} was best", before.move_to_san(&expected));
+            return None;
+        }
+
+        self.ply += 1;
+        if self.ply >= self.current().solution.len() {
+            self.finish();
+            return None;
+        }
+        let reply = self.current().solution[self.ply];
+        self.ply += 1;
+        if self.ply >= self.current().solution.len() {
+            self.finish();
+        }
+        Some(reply)
+    }
+
+    fn finish(&mut self) {
+        self.state = PuzzleState::Solved;
+        self.solved += 1;
+        self.streak += 1;
+        self.message = "Solved!".to_string();
+    }
+
+    /// Lines for the side panel.
+    pub fn status_lines(&self) -> Vec<String> {
+        let to_move = rules::colour_name(self.position_at(0).turn);
+        let prompt = match self.state {
+            PuzzleState::Solving => format!("{} to move", to_move),
+            _ => format!("{}  N: next", self.message),
+        };
+        vec![
+            format!("Puzzle {} ({}/{})", self.current().id, self.index + 1, self.puzzles.len()),
+            format!("Solved {}  Failed {}  Streak {}", self.solved, self.failed, self.streak),
+            prompt,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACK_RANK: &str = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+    const SCHOLAR: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+
+    /// FEN after `moves` in UCI notation from `fen`.
+    fn after(fen: &str, moves: &[&str]) -> String {
+        let mut position = Position::from_fen(fen).unwrap();
+        for text in moves {
+            let mv = position.parse_uci(text).unwrap();
+            position.make_move(mv);
+        }
+        position.to_fen()
+    }
+
+    #[test]
+    fn reads_csv_in_uci_and_san() {
+        let text = format!("{},a1a8\n{},Qxf7#\n", BACK_RANK, SCHOLAR);
+        let puzzles: Vec<Puzzle> = parse_csv(&text).into_iter().map(Result::unwrap).collect();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!((puzzles[0].id.as_str(), puzzles[0].fen.as_str()), ("1", BACK_RANK));
+        assert_eq!(puzzles[0].solution, vec![Position::from_fen(BACK_RANK).unwrap().parse_uci("a1a8").unwrap()]);
+        assert_eq!((puzzles[1].id.as_str(), puzzles[1].fen.as_str()), ("2", SCHOLAR));
+        assert_eq!(puzzles[1].solution, vec![Position::from_fen(SCHOLAR).unwrap().parse_uci("h5f7").unwrap()]);
+    }
+
+    #[test]
+    fn reads_epd_best_move() {
+        let puzzles = parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";\n");
+        let puzzle = puzzles.into_iter().next().unwrap().unwrap();
+        assert_eq!(puzzle.id, "back rank");
+        assert_eq!(puzzle.fen, BACK_RANK);
+        assert_eq!(puzzle.solution.iter().map(Move::to_uci).collect::<Vec<String>>(), vec!["a1a8"]);
+    }
+
+    #[test]
+    fn checks_the_solvers_move() {
+        let text = format!("{},a1a8\n{},h5f7\n", BACK_RANK, SCHOLAR);
+        let mut trainer = Trainer::new(parse_csv(&text).into_iter().map(Result::unwrap).collect());
+        assert_eq!(trainer.check_move(&after(BACK_RANK, &["a1a8"])), None);
+        assert_eq!((trainer.state, trainer.solved, trainer.streak), (PuzzleState::Solved, 1, 1));
+
+        trainer.next();
+        assert_eq!(trainer.check_move(&after(SCHOLAR, &["h5g5"])), None);
+        assert_eq!((trainer.state, trainer.failed, trainer.streak), (PuzzleState::Failed, 1, 0));
+        assert_eq!(trainer.message, "Wrong, Qxf7# was best");
+    }
+}
@@ -0,0 +1,229 @@
+//! Replay viewer for PGN files. Games are picked from a list and stepped
+//! through by hand or played back automatically. The viewer only produces
+//! FENs to show; it never touches the game being played.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::pgn::{self, PgnGame};
+use crate::rules::Position;
+
+/// Autoplay time per move to choose from, and the one used at first.
+pub const MOVE_TIMES: [Duration; 6] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_millis(1000),
+    Duration::from_millis(2000),
+    Duration::from_millis(4000),
+    Duration::from_millis(8000),
+];
+const DEFAULT_MOVE_TIME: usize = 2;
+
+/// A game prepared for replay, with the position after every move.
+pub struct ReplayGame {
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    /// Start position followed by the position after each move.
+    pub fens: Vec<String>,
+    /// Why the game stops early, if a move could not be played.
+    pub error: Option<String>,
+}
+
+pub struct Replay {
+    pub games: Vec<ReplayGame>,
+    /// Game highlighted in the list or being viewed.
+    pub selected: usize,
+    /// Whether the selected game is shown, rather than the list.
+    pub viewing: bool,
+    /// Moves played in the shown game.
+    pub ply: usize,
+    pub autoplay: bool,
+    /// Index into `MOVE_TIMES`.
+    pub move_time: usize,
+    /// Time since the last autoplay move.
+    waited: Duration,
+}
+
+impl ReplayGame {
+    pub fn new(game: &PgnGame) -> ReplayGame {
+        let mut replay = ReplayGame { headers: game.headers.clone(), moves: Vec::new(), fens: Vec::new(), error: None };
+        let mut position = match Position::from_fen(&game.start_fen()) {
+            Ok(position) => position,
+            Err(error) => {
+                replay.error = Some(format!("Bad FEN header: {}", error));
+                replay.fens.push(Position::start().to_fen());
+                return replay;
+            }
+        };
+        replay.fens.push(position.to_fen());
+        for san in game.main_line() {
+            match position.parse_san(&san) {
+                Some(mv) => {
+                    replay.moves.push(position.move_to_san(&mv));
+                    position.make_move(mv);
+                    replay.fens.push(position.to_fen());
+                }
+                None => {
+                    replay.error = Some(format!("Stops at illegal move {}", san));
+                    break;
+                }
+            }
+        }
+        replay
+    }
+
+    pub fn header(&self, name: &str) -> &str {
+        self.headers.iter().find(|(key, _)| key == name).map_or("?", |(_, value)| value.as_str())
+    }
+
+    /// List entry, e.g. "Carlsen - Caruana 1-0".
+    pub fn title(&self) -> String {
+        format!("{} - {} {}", self.header("White"), self.header("Black"), self.header("Result"))
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        let games: Vec<ReplayGame> = pgn::parse_games(&text).iter().map(ReplayGame::new).collect();
+        if games.is_empty() {
+            return Err(format!("No games in {}", path.display()));
+        }
+        Ok(Replay {
+            games,
+            selected: 0,
+            viewing: false,
+            ply: 0,
+            autoplay: false,
+            move_time: DEFAULT_MOVE_TIME,
+            waited: Duration::from_secs(0),
+        })
+    }
+
+    pub fn game(&self) -> &ReplayGame {
+        &self.games[self.selected]
+    }
+
+    /// Position to show: the selected game's start in the list, else the current move.
+    pub fn fen(&self) -> &str {
+        &self.game().fens[if self.viewing { self.ply } else { 0 }]
+    }
+
+    /// Moves the list selection by `offset` games.
+    pub fn select(&mut self, offset: isize) {
+        let last = self.games.len() as isize - 1;
+        self.selected = (self.selected as isize + offset).max(0).min(last) as usize;
+    }
+
+    pub fn open(&mut self) {
+        self.viewing = true;
+        self.ply = 0;
+        self.autoplay = false;
+        self.waited = Duration::from_secs(0);
+    }
+
+    /// Back to the game list.
+    pub fn close(&mut self) {
+        self.viewing = false;
+        self.autoplay = false;
+    }
+
+    /// Goes to move `ply`, clamped to the game.
+    pub fn go_to(&mut self, ply: usize) {
+        self.ply = ply.min(self.game().moves.len());
+        self.waited = Duration::from_secs(0);
+    }
+
+    pub fn step(&mut self, forward: bool) {
+        let ply = if forward { self.ply + 1 } else { self.ply.saturating_sub(1) };
+        self.go_to(ply);
+    }
+
+    pub fn toggle_autoplay(&mut self) {
+        self.autoplay = !self.autoplay;
+        self.waited = Duration::from_secs(0);
+        if self.autoplay && self.ply == self.game().moves.len() {
+            self.ply = 0;
+        }
+    }
+
+    /// Shortens (`faster`) or lengthens the autoplay time per move.
+    pub fn change_speed(&mut self, faster: bool) {
+        self.move_time = if faster { self.move_time.saturating_sub(1) } else { (self.move_time + 1).min(MOVE_TIMES.len() - 1) };
+    }
+
+    /// Advances autoplay by `delta`. Returns true if the shown position changed.
+    pub fn advance(&mut self, delta: Duration) -> bool {
+        if !self.viewing || !self.autoplay {
+            return false;
+        }
+        self.waited += delta;
+        if self.waited < MOVE_TIMES[self.move_time] {
+            return false;
+        }
+        self.waited = Duration::from_secs(0);
+        if self.ply == self.game().moves.len() {
+            self.autoplay = false;
+            return false;
+        }
+        self.ply += 1;
+        true
+    }
+
+    /// Last move played in the shown game, e.g. "12... Nf6".
+    pub fn last_move(&self) -> Option<String> {
+        let index = self.ply.checked_sub(1)?;
+        let black = self.game().fens[index].split_whitespace().nth(1) == Some("b");
+        let number = self.game().fens[index].split_whitespace().nth(5).unwrap_or("1");
+        Some(format!("{}{} {}", number, if black { "..." } else { "." }, self.game().moves[index]))
+    }
+
+    /// Side panel lines.
+    pub fn status_lines(&self) -> Vec<String> {
+        if !self.viewing {
+            return vec![
+                format!("Replay: {} games", self.games.len()),
+                "Up/Down choose, Enter open".to_string(),
+                "R closes the replay".to_string(),
+            ];
+        }
+        let game = self.game();
+        let playback = if self.autoplay {
+            format!("Playing, {:.2}s per move", MOVE_TIMES[self.move_time].as_secs_f64())
+        } else {
+            "Paused".to_string()
+        };
+        let mut lines = vec![
+            format!("Game {}/{}: move {}/{}", self.selected + 1, self.games.len(), self.ply, game.moves.len()),
+            self.last_move().unwrap_or("Start position".to_string()),
+            playback,
+        ];
+        if let Some(error) = &game.error {
+            lines.push(error.clone());
+        }
+        lines
+    }
+
+    /// Rows for the panel below the status lines: the game list, or the shown game's headers.
+    pub fn rows(&self, count: usize) -> Vec<String> {
+        if self.viewing {
+            return self.game().headers.iter()
+                .filter(|(key, _)| key != "FEN" && key != "SetUp")
+                .take(count)
+                .map(|(key, value)| format!("{}: {}", key, value))
+                .collect();
+        }
+        // Scrolls so the selected game stays in view.
+        let first = (self.selected + 1).saturating_sub(count);
+        self.games.iter().enumerate().skip(first).take(count)
+            .map(|(index, game)| format!("{}{}. {}", if index == self.selected { "> " } else { "  " }, index + 1, game.title()))
+            .collect()
+    }
+
+    /// Game shown in list row `row`, as laid out by `rows`.
+    pub fn game_at_row(&self, row: usize, count: usize) -> Option<usize> {
+        let index = (self.selected + 1).saturating_sub(count) + row;
+        if row < count && index < self.games.len() { Some(index) } else { None }
+    }
+}
@@ -0,0 +1,295 @@
+//! Post-game report. A UCI engine evaluates every position of a game to a
+//! fixed depth. Each move gets its centipawn loss and is judged an inaccuracy,
+//! mistake or blunder by how much it lowered the mover's winning chance. The
+//! evaluations also give each side an accuracy percentage and the graph of the
+//! results view, and can be written into the game as PGN annotations.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::pgn::GameRecord;
+use crate::rules::{self, Position, Status};
+use crate::tree::{nag_symbol, MoveTree};
+use crate::uci::{Engine, Score};
+
+/// Search depth per position without `--depth`.
+pub const DEFAULT_DEPTH: u32 = 12;
+/// Centipawns a mate counts as, which also caps the loss of a single move.
+const MATE_CENTIPAWNS: i32 = 1000;
+/// How often `run` checks the engine for output.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveReview {
+    pub san: String,
+    /// Move number, as in the FEN before the move.
+    pub fullmove: u32,
+    pub white: bool,
+    /// Centipawns lost against the evaluation before the move.
+    pub loss: i32,
+    /// Between 0 and 100, from the drop in winning chance.
+    pub accuracy: f32,
+    pub judgement: Option<Judgement>,
+    /// The engine's move in SAN, if the played move was judged worse.
+    pub best: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// Evaluation of every position from White's side, the start position first.
+    pub evals: Vec<Score>,
+    pub moves: Vec<MoveReview>,
+}
+
+/// Runs the engine over a game one position at a time, so the GUI can keep drawing.
+pub struct Review {
+    engine: Engine,
+    record: GameRecord,
+    depth: u32,
+    chess960: bool,
+    /// Whether the engine is searching the position after the last evaluated one.
+    started: bool,
+    evals: Vec<Score>,
+    best_moves: Vec<Option<String>>,
+}
+
+impl Judgement {
+    /// Judgement of a move that lowered the mover's winning chance by `drop`, between 0 and 1.
+    pub fn from_drop(drop: f32) -> Option<Judgement> {
+        if drop >= 0.3 {
+            Some(Judgement::Blunder)
+        } else if drop >= 0.2 {
+            Some(Judgement::Mistake)
+        } else if drop >= 0.1 {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    /// NAG of the judgement: ?!, ? or ??.
+    pub fn nag(&self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+}
+
+/// Score in centipawns, mates counting as `MATE_CENTIPAWNS`.
+fn centipawns(score: Score) -> i32 {
+    match score {
+        Score::Centipawns(cp) => cp.clamp(-MATE_CENTIPAWNS, MATE_CENTIPAWNS),
+        Score::Mate(moves) => if moves > 0 { MATE_CENTIPAWNS } else { -MATE_CENTIPAWNS },
+    }
+}
+
+/// Evaluation as written in `[%eval]` comments, e.g. "0.35" or "#-3".
+fn eval_text(score: Score) -> String {
+    match score {
+        Score::Centipawns(cp) => format!("{:.2}", cp as f32 / 100.0),
+        Score::Mate(moves) => format!("#{}", moves),
+    }
+}
+
+/// White's score in a position the game ended in, which engines do not search.
+fn final_score(position: &Position) -> Option<Score> {
+    match position.status() {
+        Status::Checkmate if position.turn == rules::WHITE => Some(Score::Centipawns(-MATE_CENTIPAWNS)),
+        Status::Checkmate => Some(Score::Centipawns(MATE_CENTIPAWNS)),
+        Status::Stalemate => Some(Score::Centipawns(0)),
+        _ => None,
+    }
+}
+
+impl Report {
+    /// Reviews the moves of `record` from `evals`, White's score of each position, and
+    /// `best_moves`, the engine's move in SAN in each position.
+    pub fn new(record: &GameRecord, evals: Vec<Score>, best_moves: &[Option<String>]) -> Report {
+        let mut moves = Vec::new();
+        for (i, san) in record.moves.iter().enumerate() {
+            let fields: Vec<&str> = record.fens[i].split_whitespace().collect();
+            let white = fields.get(1) != Some(&"b");
+            let fullmove = fields.get(5).and_then(|number| number.parse().ok()).unwrap_or(1);
+            let sign = if white { 1 } else { -1 };
+            let (before, after) = (sign * centipawns(evals[i]), sign * centipawns(evals[i + 1]));
+            let loss = (before - after).clamp(0, MATE_CENTIPAWNS);
+            let chance = |cp: i32| Score::Centipawns(cp).winning_chance();
+            let drop = (chance(before) - chance(after)).max(0.0);
+            // Lichess' fit of move accuracy to the drop in winning chance, in percentage points.
+            let accuracy = (103.1668 * (-0.04354 * drop * 100.0).exp() - 3.1669).clamp(0.0, 100.0);
+            let judgement = Judgement::from_drop(drop);
+            let best = best_moves.get(i).cloned().flatten().filter(|best| judgement.is_some() && best != san);
+            moves.push(MoveReview { san: san.clone(), fullmove, white, loss, accuracy, judgement, best });
+        }
+        Report { evals, moves }
+    }
+
+    fn side_moves(&self, white: bool) -> impl Iterator<Item = &MoveReview> {
+        self.moves.iter().filter(move |review| review.white == white)
+    }
+
+    /// Mean accuracy of one side's moves, 100 without any.
+    pub fn accuracy(&self, white: bool) -> f32 {
+        let count = self.side_moves(white).count();
+        if count == 0 { 100.0 } else { self.side_moves(white).map(|review| review.accuracy).sum::<f32>() / count as f32 }
+    }
+
+    /// Mean centipawn loss of one side's moves.
+    pub fn average_loss(&self, white: bool) -> f32 {
+        let count = self.side_moves(white).count().max(1);
+        self.side_moves(white).map(|review| review.loss).sum::<i32>() as f32 / count as f32
+    }
+
+    pub fn count(&self, white: bool, judgement: Judgement) -> usize {
+        self.side_moves(white).filter(|review| review.judgement == Some(judgement)).count()
+    }
+
+    /// Accuracy and judgement counts per side, then the judged moves, e.g. "12... Qxb2?? best Nf3".
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (white, name) in [(true, "White"), (false, "Black")].iter() {
+            lines.push(format!("{} {:.0}%, avg loss {:.0}", name, self.accuracy(*white), self.average_loss(*white)));
+            lines.push(format!("  ?! {}  ? {}  ?? {}",
+                self.count(*white, Judgement::Inaccuracy), self.count(*white, Judgement::Mistake), self.count(*white, Judgement::Blunder)));
+        }
+        for review in self.moves.iter() {
+            if let Some(judgement) = review.judgement {
+                let number = format!("{}{}", review.fullmove, if review.white { "." } else { "..." });
+                let symbol = nag_symbol(judgement.nag()).unwrap_or("");
+                let best = review.best.as_ref().map_or(String::new(), |best| format!(" best {}", best));
+                lines.push(format!("{} {}{}{}", number, review.san, symbol, best));
+            }
+        }
+        lines
+    }
+
+    /// Adds the judgements as NAGs and the evaluations and better moves as comments to the
+    /// main line of `tree`, which must hold the reviewed game.
+    pub fn annotate(&self, tree: &mut MoveTree) {
+        let main_line = tree.path(tree.line_end(0));
+        for ((node, review), eval) in main_line.into_iter().zip(self.moves.iter()).zip(self.evals[1..].iter()) {
+            let mut comment = format!("[%eval {}]", eval_text(*eval));
+            if let Some(judgement) = review.judgement {
+                comment.push_str(&format!(" {}.", judgement.name()));
+                if let Some(best) = &review.best {
+                    comment.push_str(&format!(" {} was best.", best));
+                }
+                let nags = &mut tree.nodes[node].nags;
+                nags.retain(|nag| nag_symbol(*nag).is_none());
+                nags.insert(0, judgement.nag());
+            }
+            let text = &mut tree.nodes[node].comment;
+            *text = if text.is_empty() { comment } else { format!("{} {}", comment, text) };
+        }
+    }
+}
+
+impl Review {
+    /// Starts the engine at `engine_path` on the game in `record`, searching each position to `depth`.
+    pub fn start(engine_path: &str, record: GameRecord, depth: u32, chess960: bool) -> Result<Review, String> {
+        let mut engine = Engine::start(engine_path).map_err(|error| format!("Failed to start engine {}: {}", engine_path, error))?;
+        engine.send("ucinewgame").map_err(|error| error.to_string())?;
+        Ok(Review { engine, record, depth, chess960, started: false, evals: Vec::new(), best_moves: Vec::new() })
+    }
+
+    /// Positions evaluated so far and in total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.evals.len(), self.record.fens.len())
+    }
+
+    /// Reads the engine's output and starts the next search. Returns the report once every
+    /// position has been evaluated.
+    pub fn poll(&mut self) -> Result<Option<Report>, String> {
+        loop {
+            let index = self.evals.len();
+            if index == self.record.fens.len() {
+                return Ok(Some(Report::new(&self.record, self.evals.clone(), &self.best_moves)));
+            }
+            let position = Position::from_fen(&self.record.fens[index])?;
+            if let Some(score) = final_score(&position) {
+                self.evals.push(score);
+                self.best_moves.push(None);
+                continue;
+            }
+            self.engine.poll();
+            if self.engine.is_searching() {
+                return Ok(None);
+            }
+            if !self.started {
+                let limits = format!("depth {}", self.depth);
+                self.engine.go(&self.record.fens[index], self.chess960, &limits).map_err(|error| error.to_string())?;
+                self.started = true;
+                return Ok(None);
+            }
+            let score = self.engine.analysis.score.unwrap_or(Score::Centipawns(0));
+            self.evals.push(score.for_white(position.turn == rules::WHITE));
+            let best = self.engine.best_move.take().and_then(|text| position.parse_uci(&text));
+            self.best_moves.push(best.map(|mv| position.move_to_san(&mv)));
+            self.started = false;
+        }
+    }
+}
+
+/// Reviews the game in `record` without the GUI, waiting for the engine.
+pub fn run(engine_path: &str, record: GameRecord, depth: u32, chess960: bool) -> Result<Report, String> {
+    let mut review = Review::start(engine_path, record, depth, chess960)?;
+    loop {
+        if let Some(report) = review.poll()? {
+            return Ok(report);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judges_moves_from_fixed_evaluations() {
+        let mut record = GameRecord::new(rules::START_FEN.to_string());
+        let mut position = Position::start();
+        for uci in ["e2e4", "e7e5", "d1h5", "g8f6"].iter() {
+            let mv = position.parse_uci(uci).unwrap();
+            let san = position.move_to_san(&mv);
+            position.make_move(mv);
+            record.push(san, position.to_fen());
+        }
+        let evals = vec![Score::Centipawns(30), Score::Centipawns(30), Score::Centipawns(20), Score::Centipawns(-80), Score::Mate(1)];
+        let best_moves = vec![None, None, Some("Nf3".to_string()), Some("Nc6".to_string())];
+        let report = Report::new(&record, evals, &best_moves);
+
+        let losses: Vec<i32> = report.moves.iter().map(|review| review.loss).collect();
+        assert_eq!(losses, vec![0, 0, 100, 1000]);
+        let judgements: Vec<Option<Judgement>> = report.moves.iter().map(|review| review.judgement).collect();
+        assert_eq!(judgements, vec![None, None, None, Some(Judgement::Blunder)]);
+        assert_eq!(report.moves[3].best.as_deref(), Some("Nc6"));
+        assert_eq!(report.moves[2].best, None);
+        assert_eq!((report.moves[3].fullmove, report.moves[3].white), (2, false));
+
+        assert!((report.moves[0].accuracy - 100.0).abs() < 0.01);
+        assert!((report.moves[2].accuracy - 66.1).abs() < 0.1);
+        assert!(report.moves[3].accuracy < 10.0);
+        assert_eq!(report.count(false, Judgement::Blunder), 1);
+        assert!((report.average_loss(false) - 500.0).abs() < 0.01);
+        assert!(report.accuracy(true) > report.accuracy(false));
+        assert!(report.lines().contains(&"2... Nf6?? best Nc6".to_string()));
+    }
+}
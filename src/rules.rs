@@ -0,0 +1,809 @@
+//! Position model with FEN support and legal move generation.
+//!
+//! The board uses the same encoding as `AppState`: index `rank*8+file` and
+//! each square is `[colour, piece]` with colour 8 (white) / 16 (black).
+
+pub const WHITE: u8 = 8;
+pub const BLACK: u8 = 16;
+
+pub const PAWN: u8 = 1;
+pub const KNIGHT: u8 = 2;
+pub const BISHOP: u8 = 3;
+pub const ROOK: u8 = 4;
+pub const QUEEN: u8 = 5;
+pub const KING: u8 = 6;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+pub const KING_STEPS: [(i8, i8); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+/// Index into `Position::castling`.
+pub const WHITE_KING_SIDE: usize = 0;
+pub const WHITE_QUEEN_SIDE: usize = 1;
+pub const BLACK_KING_SIDE: usize = 2;
+pub const BLACK_QUEEN_SIDE: usize = 3;
+
+/// A move between two square indices. `promotion` is 0 unless a pawn promotes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: u8,
+}
+
+/// Outcome of the side to move having (or lacking) legal moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    InProgress,
+    Check,
+    Checkmate,
+    Stalemate,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub board: [[u8; 2]; 64],
+    pub turn: u8,
+    /// File of the rook each castling right belongs to, see `WHITE_KING_SIDE` etc.
+    pub castling: [Option<u8>; 4],
+    pub en_passant: Option<u8>,
+    pub halfmove: u32,
+    pub fullmove: u32,
+    /// Chess960 rules: castling is written as the king taking its own rook,
+    /// and FENs use X-FEN castling fields.
+    pub chess960: bool,
+}
+
+pub fn opponent(colour: u8) -> u8 {
+    if colour == WHITE { BLACK } else { WHITE }
+}
+
+pub fn colour_name(colour: u8) -> String {
+    if colour == WHITE { "White".to_string() } else { "Black".to_string() }
+}
+
+/// Square index to algebraic name, e.g. 12 -> "e2".
+pub fn square_name(sq: u8) -> String {
+    format!("{}{}", (b'a' + sq % 8) as char, sq / 8 + 1)
+}
+
+/// Algebraic name to square index, e.g. "e2" -> 12.
+pub fn parse_square(name: &str) -> Option<u8> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    Some((bytes[1] - b'1') * 8 + (bytes[0] - b'a'))
+}
+
+pub fn piece_symbol(piece: u8) -> char {
+    match piece {
+        PAWN => 'p',
+        KNIGHT => 'n',
+        BISHOP => 'b',
+        ROOK => 'r',
+        QUEEN => 'q',
+        KING => 'k',
+        _ => ' ',
+    }
+}
+
+pub fn piece_from_symbol(c: char) -> u8 {
+    match c.to_ascii_lowercase() {
+        'p' => PAWN,
+        'n' => KNIGHT,
+        'b' => BISHOP,
+        'r' => ROOK,
+        'q' => QUEEN,
+        'k' => KING,
+        _ => 0,
+    }
+}
+
+/// The placement, side to move, castling and en passant fields of a FEN,
+/// which identify a position regardless of the move counters.
+pub fn position_key(fen: &str) -> String {
+    fen.split_whitespace().take(4).collect::<Vec<&str>>().join(" ")
+}
+
+/// Back rank pieces, a-file first, of Chess960 start position `number` (Scharnagl numbering).
+pub fn chess960_back_rank(number: u16) -> [u8; 8] {
+    const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+    let mut rank = [0u8; 8];
+    let mut n = number as usize % 960;
+
+    rank[(n % 4) * 2 + 1] = BISHOP;
+    n /= 4;
+    rank[(n % 4) * 2] = BISHOP;
+    n /= 4;
+
+    let empty = |rank: &[u8; 8]| -> Vec<usize> { (0..8).filter(|file| rank[*file] == 0).collect() };
+    rank[empty(&rank)[n % 6]] = QUEEN;
+    n /= 6;
+
+    let (first, second) = KNIGHT_PLACEMENTS[n];
+    let files = empty(&rank);
+    rank[files[first]] = KNIGHT;
+    rank[files[second]] = KNIGHT;
+
+    let files = empty(&rank);
+    rank[files[0]] = ROOK;
+    rank[files[1]] = KING;
+    rank[files[2]] = ROOK;
+    rank
+}
+
+/// Deterministic pseudo-random key number `index` for Zobrist hashing (splitmix64).
+fn zobrist_key(index: u64) -> u64 {
+    let mut z = (index + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn offset(sq: u8, df: i8, dr: i8) -> Option<u8> {
+    let file = (sq % 8) as i8 + df;
+    let rank = (sq / 8) as i8 + dr;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as u8)
+    } else {
+        None
+    }
+}
+
+impl Move {
+    /// Long algebraic form used by UCI, e.g. "e7e8q".
+    pub fn to_uci(&self) -> String {
+        let mut text = format!("{}{}", square_name(self.from), square_name(self.to));
+        if self.promotion != 0 {
+            text.push(piece_symbol(self.promotion));
+        }
+        text
+    }
+}
+
+impl Position {
+    pub fn empty() -> Position {
+        Position {
+            board: [[0, 0]; 64],
+            turn: WHITE,
+            castling: [None; 4],
+            en_passant: None,
+            halfmove: 0,
+            fullmove: 1,
+            chess960: false,
+        }
+    }
+
+    pub fn start() -> Position {
+        Position::from_fen(START_FEN).unwrap()
+    }
+
+    /// Chess960 start position `number` (0-959) in Scharnagl's numbering, 518 being the standard one.
+    pub fn chess960(number: u16) -> Position {
+        let back_rank = chess960_back_rank(number);
+        let mut position = Position::empty();
+        for (file, piece) in back_rank.iter().enumerate() {
+            position.board[file] = [WHITE, *piece];
+            position.board[8 + file] = [WHITE, PAWN];
+            position.board[48 + file] = [BLACK, PAWN];
+            position.board[56 + file] = [BLACK, *piece];
+        }
+        let rooks: Vec<u8> = (0..8u8).filter(|file| back_rank[*file as usize] == ROOK).collect();
+        position.castling = [Some(rooks[1]), Some(rooks[0]), Some(rooks[1]), Some(rooks[0])];
+        position.chess960 = true;
+        position
+    }
+
+    /// Parses a FEN string. Missing trailing fields fall back to their defaults.
+    pub fn from_fen(fen: &str) -> Result<Position, String> {
+        let mut position = Position::empty();
+        let mut fields = fen.split_whitespace();
+
+        let placement = fields.next().ok_or("Empty FEN")?;
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("Expected 8 ranks, found {}", ranks.len()));
+        }
+        for (i, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - i;
+            let mut file = 0;
+            for c in rank_text.chars() {
+                if let Some(num) = c.to_digit(10) {
+                    file += num as usize;
+                } else {
+                    let piece = piece_from_symbol(c);
+                    if piece == 0 {
+                        return Err(format!("Unknown piece '{}'", c));
+                    }
+                    if file > 7 {
+                        return Err(format!("Rank {} is too long", rank + 1));
+                    }
+                    let colour = if c.is_uppercase() { WHITE } else { BLACK };
+                    position.board[rank * 8 + file] = [colour, piece];
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return Err(format!("Rank {} does not have 8 files", rank + 1));
+            }
+        }
+
+        position.turn = match fields.next().unwrap_or("w") {
+            "w" => WHITE,
+            "b" => BLACK,
+            other => return Err(format!("Unknown side to move '{}'", other)),
+        };
+
+        let castling = fields.next().unwrap_or("-");
+        if castling != "-" {
+            for c in castling.chars() {
+                let colour = if c.is_ascii_uppercase() { WHITE } else { BLACK };
+                let side_base = if colour == WHITE { WHITE_KING_SIDE } else { BLACK_KING_SIDE };
+                let king_file = position.king_square(colour).map_or(4, |sq| sq % 8);
+                let (side, file) = match c.to_ascii_lowercase() {
+                    'k' => (side_base, position.outermost_rook(colour, true).unwrap_or(7)),
+                    'q' => (side_base + 1, position.outermost_rook(colour, false).unwrap_or(0)),
+                    // Shredder-FEN names the rook's file.
+                    file @ 'a'..='h' => {
+                        let file = file as u8 - b'a';
+                        (if file > king_file { side_base } else { side_base + 1 }, file)
+                    }
+                    _ => return Err(format!("Unknown castling right '{}'", c)),
+                };
+                position.castling[side] = Some(file);
+            }
+            // Castling rights for pieces off the standard squares only make sense in Chess960.
+            position.chess960 = (0..4).any(|side| {
+                let colour = if side < 2 { WHITE } else { BLACK };
+                let standard_file = if side % 2 == 0 { 7 } else { 0 };
+                position.castling[side].is_some_and(|file| file != standard_file)
+                    || (position.castling[side].is_some() && position.king_square(colour).is_some_and(|sq| sq % 8 != 4))
+            });
+        }
+
+        let en_passant = fields.next().unwrap_or("-");
+        if en_passant != "-" {
+            position.en_passant = Some(parse_square(en_passant).ok_or(format!("Bad en passant square '{}'", en_passant))?);
+        }
+
+        position.halfmove = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+        position.fullmove = fields.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+
+        Ok(position)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let [colour, piece] = self.board[rank * 8 + file];
+                if piece == 0 {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let symbol = piece_symbol(piece);
+                fen.push(if colour == WHITE { symbol.to_ascii_uppercase() } else { symbol });
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if self.turn == WHITE { " w " } else { " b " });
+        fen.push_str(&self.castling_text());
+        fen.push(' ');
+        match self.en_passant {
+            Some(sq) => fen.push_str(&square_name(sq)),
+            None => fen.push('-'),
+        }
+        fen.push_str(&format!(" {} {}", self.halfmove, self.fullmove));
+        fen
+    }
+
+    /// File of the outermost rook on the back rank, on the king or queen side of the king.
+    fn outermost_rook(&self, colour: u8, king_side: bool) -> Option<u8> {
+        let back_rank = if colour == WHITE { 0 } else { 7 };
+        let king_file = self.king_square(colour)? % 8;
+        let rook_files = (0..8u8).filter(|file| self.board[(back_rank * 8 + file) as usize] == [colour, ROOK]);
+        if king_side {
+            rook_files.filter(|file| *file > king_file).max()
+        } else {
+            rook_files.filter(|file| *file < king_file).min()
+        }
+    }
+
+    /// Castling rights as written in a FEN, e.g. "KQkq" or "-". In Chess960 a right whose rook
+    /// is not the outermost one is written with the rook's file, as X-FEN does.
+    pub fn castling_text(&self) -> String {
+        let mut text = String::new();
+        for (i, symbol) in ['K', 'Q', 'k', 'q'].iter().enumerate() {
+            if let Some(file) = self.castling[i] {
+                let colour = if i < 2 { WHITE } else { BLACK };
+                if self.chess960 && self.outermost_rook(colour, i % 2 == 0) != Some(file) {
+                    let letter = (b'a' + file) as char;
+                    text.push(if colour == WHITE { letter.to_ascii_uppercase() } else { letter });
+                } else {
+                    text.push(*symbol);
+                }
+            }
+        }
+        if text.is_empty() {
+            text.push('-');
+        }
+        text
+    }
+
+    /// Zobrist hash of the position. The en passant square only counts when a pawn can take on it,
+    /// so transpositions hash equally.
+    pub fn zobrist(&self) -> u64 {
+        let mut hash = 0;
+        for sq in 0..64 {
+            let [colour, piece] = self.board[sq];
+            if piece != 0 {
+                let colour_index = if colour == WHITE { 0 } else { 1 };
+                hash ^= zobrist_key(((colour_index * 6 + piece as usize - 1) * 64 + sq) as u64);
+            }
+        }
+        if self.turn == BLACK {
+            hash ^= zobrist_key(768);
+        }
+        for side in 0..4 {
+            if self.castling[side].is_some() {
+                hash ^= zobrist_key(769 + side as u64);
+            }
+        }
+        if let Some(sq) = self.en_passant {
+            let pawn_rank = if self.turn == WHITE { -1 } else { 1 };
+            let capturable = [-1, 1].iter()
+                .filter_map(|df| offset(sq, *df, pawn_rank))
+                .any(|from| self.board[from as usize] == [self.turn, PAWN]);
+            if capturable {
+                hash ^= zobrist_key(773 + (sq % 8) as u64);
+            }
+        }
+        hash
+    }
+
+    pub fn king_square(&self, colour: u8) -> Option<u8> {
+        (0..64u8).find(|&sq| self.board[sq as usize] == [colour, KING])
+    }
+
+    /// Returns true if any piece of colour `by` attacks `sq`.
+    pub fn is_attacked(&self, sq: u8, by: u8) -> bool {
+        let pawn_rank = if by == WHITE { -1 } else { 1 };
+        for df in [-1, 1].iter() {
+            if let Some(from) = offset(sq, *df, pawn_rank) {
+                if self.board[from as usize] == [by, PAWN] {
+                    return true;
+                }
+            }
+        }
+        for (df, dr) in KNIGHT_STEPS.iter() {
+            if let Some(from) = offset(sq, *df, *dr) {
+                if self.board[from as usize] == [by, KNIGHT] {
+                    return true;
+                }
+            }
+        }
+        for (df, dr) in KING_STEPS.iter() {
+            if let Some(from) = offset(sq, *df, *dr) {
+                if self.board[from as usize] == [by, KING] {
+                    return true;
+                }
+            }
+        }
+        for (directions, slider) in [(ROOK_DIRECTIONS, ROOK), (BISHOP_DIRECTIONS, BISHOP)].iter() {
+            for (df, dr) in directions.iter() {
+                let mut current = sq;
+                while let Some(next) = offset(current, *df, *dr) {
+                    let [colour, piece] = self.board[next as usize];
+                    if piece != 0 {
+                        if colour == by && (piece == *slider || piece == QUEEN) {
+                            return true;
+                        }
+                        break;
+                    }
+                    current = next;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn in_check(&self, colour: u8) -> bool {
+        match self.king_square(colour) {
+            Some(sq) => self.is_attacked(sq, opponent(colour)),
+            None => false,
+        }
+    }
+
+    fn push_pawn_move(moves: &mut Vec<Move>, from: u8, to: u8) {
+        if to / 8 == 0 || to / 8 == 7 {
+            for piece in [QUEEN, ROOK, BISHOP, KNIGHT].iter() {
+                moves.push(Move { from, to, promotion: *piece });
+            }
+        } else {
+            moves.push(Move { from, to, promotion: 0 });
+        }
+    }
+
+    /// Moves that follow piece movement rules but may leave the king in check.
+    pub fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let us = self.turn;
+
+        for from in 0..64u8 {
+            let [colour, piece] = self.board[from as usize];
+            if colour != us {
+                continue;
+            }
+            match piece {
+                PAWN => {
+                    let forward = if us == WHITE { 1 } else { -1 };
+                    let start_rank = if us == WHITE { 1 } else { 6 };
+                    if let Some(to) = offset(from, 0, forward) {
+                        if self.board[to as usize][1] == 0 {
+                            Position::push_pawn_move(&mut moves, from, to);
+                            if from / 8 == start_rank {
+                                let double = offset(to, 0, forward).unwrap();
+                                if self.board[double as usize][1] == 0 {
+                                    moves.push(Move { from, to: double, promotion: 0 });
+                                }
+                            }
+                        }
+                    }
+                    for df in [-1, 1].iter() {
+                        if let Some(to) = offset(from, *df, forward) {
+                            let target = self.board[to as usize];
+                            if (target[1] != 0 && target[0] != us) || self.en_passant == Some(to) {
+                                Position::push_pawn_move(&mut moves, from, to);
+                            }
+                        }
+                    }
+                }
+                KNIGHT | KING => {
+                    let steps = if piece == KNIGHT { &KNIGHT_STEPS } else { &KING_STEPS };
+                    for (df, dr) in steps.iter() {
+                        if let Some(to) = offset(from, *df, *dr) {
+                            if self.board[to as usize][0] != us {
+                                moves.push(Move { from, to, promotion: 0 });
+                            }
+                        }
+                    }
+                }
+                BISHOP | ROOK | QUEEN => {
+                    let mut directions = Vec::new();
+                    if piece != BISHOP {
+                        directions.extend_from_slice(&ROOK_DIRECTIONS);
+                    }
+                    if piece != ROOK {
+                        directions.extend_from_slice(&BISHOP_DIRECTIONS);
+                    }
+                    for (df, dr) in directions {
+                        let mut current = from;
+                        while let Some(to) = offset(current, df, dr) {
+                            let [colour, target] = self.board[to as usize];
+                            if colour == us {
+                                break;
+                            }
+                            moves.push(Move { from, to, promotion: 0 });
+                            if target != 0 {
+                                break;
+                            }
+                            current = to;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        moves.extend(self.castling_moves());
+        moves
+    }
+
+    /// Castling moves for the side to move, already checked for attacked squares.
+    fn castling_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let us = self.turn;
+        let king = match self.king_square(us) {
+            Some(sq) => sq,
+            None => return moves,
+        };
+        let back_rank = if us == WHITE { 0 } else { 7 };
+        if king / 8 != back_rank || self.in_check(us) {
+            return moves;
+        }
+        let sides = if us == WHITE { [WHITE_KING_SIDE, WHITE_QUEEN_SIDE] } else { [BLACK_KING_SIDE, BLACK_QUEEN_SIDE] };
+
+        for side in sides.iter() {
+            let rook_file = match self.castling[*side] {
+                Some(file) => file,
+                None => continue,
+            };
+            let rook = back_rank * 8 + rook_file;
+            if self.board[rook as usize] != [us, ROOK] {
+                continue;
+            }
+            let king_side = *side == WHITE_KING_SIDE || *side == BLACK_KING_SIDE;
+            let king_to = back_rank * 8 + if king_side { 6 } else { 2 };
+            let rook_to = back_rank * 8 + if king_side { 5 } else { 3 };
+
+            // Every square the king or rook passes over must be empty apart from the two pieces themselves.
+            let low = *[king, king_to, rook, rook_to].iter().min().unwrap();
+            let high = *[king, king_to, rook, rook_to].iter().max().unwrap();
+            let blocked = (low..=high).any(|sq| sq != king && sq != rook && self.board[sq as usize][1] != 0);
+            if blocked {
+                continue;
+            }
+
+            let (path_low, path_high) = if king < king_to { (king, king_to) } else { (king_to, king) };
+            let attacked = (path_low..=path_high).any(|sq| self.is_attacked(sq, opponent(us)));
+            if attacked {
+                continue;
+            }
+
+            moves.push(Move { from: king, to: if self.chess960 { rook } else { king_to }, promotion: 0 });
+        }
+        moves
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|mv| {
+                let mut next = self.clone();
+                next.make_move(*mv);
+                !next.in_check(self.turn)
+            })
+            .collect()
+    }
+
+    pub fn legal_moves_from(&self, from: u8) -> Vec<Move> {
+        self.legal_moves().into_iter().filter(|mv| mv.from == from).collect()
+    }
+
+    /// Returns the castling right index if `mv` is a castling move.
+    pub fn castling_side(&self, mv: &Move) -> Option<usize> {
+        let [colour, piece] = self.board[mv.from as usize];
+        if piece != KING {
+            return None;
+        }
+        if self.chess960 {
+            if self.board[mv.to as usize] != [colour, ROOK] {
+                return None;
+            }
+        } else if (mv.to as i8 - mv.from as i8).abs() != 2 {
+            return None;
+        }
+        let king_side = mv.to > mv.from;
+        Some(match (colour == WHITE, king_side) {
+            (true, true) => WHITE_KING_SIDE,
+            (true, false) => WHITE_QUEEN_SIDE,
+            (false, true) => BLACK_KING_SIDE,
+            (false, false) => BLACK_QUEEN_SIDE,
+        })
+    }
+
+    pub fn is_capture(&self, mv: &Move) -> bool {
+        let [colour, piece] = self.board[mv.to as usize];
+        (piece != 0 && colour != self.turn) || (self.board[mv.from as usize][1] == PAWN && self.en_passant == Some(mv.to))
+    }
+
+    /// Plays `mv` without checking that it is legal.
+    pub fn make_move(&mut self, mv: Move) {
+        let us = self.turn;
+        let [_, piece] = self.board[mv.from as usize];
+        let capture = self.is_capture(&mv);
+
+        if let Some(side) = self.castling_side(&mv) {
+            let back_rank = mv.from / 8;
+            let king_side = side % 2 == 0;
+            let rook = back_rank * 8 + self.castling[side].unwrap_or(if king_side { 7 } else { 0 });
+            let king_to = back_rank * 8 + if king_side { 6 } else { 2 };
+            let rook_to = back_rank * 8 + if king_side { 5 } else { 3 };
+            self.board[rook as usize] = [0, 0];
+            self.board[mv.from as usize] = [0, 0];
+            self.board[rook_to as usize] = [us, ROOK];
+            self.board[king_to as usize] = [us, KING];
+        } else {
+            if piece == PAWN && self.en_passant == Some(mv.to) && self.board[mv.to as usize][1] == 0 {
+                let captured = if us == WHITE { mv.to - 8 } else { mv.to + 8 };
+                self.board[captured as usize] = [0, 0];
+            }
+            self.board[mv.to as usize] = [us, if mv.promotion != 0 { mv.promotion } else { piece }];
+            self.board[mv.from as usize] = [0, 0];
+        }
+
+        // Moving the king or a castling rook, or capturing that rook, removes the right.
+        for side in 0..4 {
+            if let Some(file) = self.castling[side] {
+                let colour = if side < 2 { WHITE } else { BLACK };
+                let back_rank = if colour == WHITE { 0 } else { 7 };
+                let rook = back_rank * 8 + file;
+                if (colour == us && piece == KING) || mv.from == rook || mv.to == rook {
+                    self.castling[side] = None;
+                }
+            }
+        }
+
+        self.en_passant = None;
+        if piece == PAWN && (mv.to as i8 - mv.from as i8).abs() == 16 {
+            self.en_passant = Some((mv.from + mv.to) / 2);
+        }
+
+        if piece == PAWN || capture {
+            self.halfmove = 0;
+        } else {
+            self.halfmove += 1;
+        }
+        if us == BLACK {
+            self.fullmove += 1;
+        }
+        self.turn = opponent(us);
+    }
+
+    /// Resolves a UCI move such as "e7e8q" against the legal moves.
+    pub fn parse_uci(&self, text: &str) -> Option<Move> {
+        let from = parse_square(text.get(0..2)?)?;
+        let to = parse_square(text.get(2..4)?)?;
+        let promotion = text.get(4..).and_then(|rest| rest.chars().next()).map_or(0, piece_from_symbol);
+        self.legal_moves().into_iter().find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+    }
+
+    /// Standard algebraic notation for a legal move, e.g. "Nbd7", "exd5", "O-O" or "e8=Q#".
+    pub fn move_to_san(&self, mv: &Move) -> String {
+        let mut san = self.san_without_check(mv, &self.legal_moves());
+        let mut next = self.clone();
+        next.make_move(*mv);
+        match next.status() {
+            Status::Checkmate => san.push('#'),
+            Status::Check => san.push('+'),
+            _ => {}
+        }
+        san
+    }
+
+    /// SAN of a legal move without the check or mate marker, disambiguated against `legal`.
+    pub fn san_without_check(&self, mv: &Move, legal: &[Move]) -> String {
+        let piece = self.board[mv.from as usize][1];
+        let mut san = String::new();
+
+        if let Some(side) = self.castling_side(mv) {
+            san.push_str(if side % 2 == 0 { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.is_capture(mv);
+            if piece == PAWN {
+                if capture {
+                    san.push((b'a' + mv.from % 8) as char);
+                }
+            } else {
+                san.push(piece_symbol(piece).to_ascii_uppercase());
+                let others: Vec<&Move> = legal
+                    .iter()
+                    .filter(|other| other.to == mv.to && other.from != mv.from && self.board[other.from as usize][1] == piece)
+                    .collect();
+                if !others.is_empty() {
+                    let same_file = others.iter().any(|other| other.from % 8 == mv.from % 8);
+                    let same_rank = others.iter().any(|other| other.from / 8 == mv.from / 8);
+                    let name = square_name(mv.from);
+                    if !same_file {
+                        san.push_str(&name[0..1]);
+                    } else if !same_rank {
+                        san.push_str(&name[1..2]);
+                    } else {
+                        san.push_str(&name);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+            if mv.promotion != 0 {
+                san.push('=');
+                san.push(piece_symbol(mv.promotion).to_ascii_uppercase());
+            }
+        }
+        san
+    }
+
+    /// Resolves a SAN move such as "Nf3", "exd6", "O-O" or "e8=Q+" against the legal moves.
+    pub fn parse_san(&self, text: &str) -> Option<Move> {
+        let normalise = |san: &str| -> String {
+            let san = if san.starts_with('0') { san.replace('0', "O") } else { san.to_string() };
+            san.chars().filter(|c| !"+#!?=".contains(*c)).collect()
+        };
+        let wanted = normalise(text);
+        let legal = self.legal_moves();
+        legal.iter().copied().find(|mv| normalise(&self.san_without_check(mv, &legal)) == wanted)
+    }
+
+    /// Resolves a move written either in UCI or in SAN.
+    pub fn parse_move(&self, text: &str) -> Option<Move> {
+        self.parse_uci(text).or_else(|| self.parse_san(text))
+    }
+
+    /// Converts a line of UCI moves to numbered SAN, stopping at the first illegal move.
+    pub fn line_to_san(&self, uci_moves: &[String]) -> String {
+        let mut position = self.clone();
+        let mut words: Vec<String> = Vec::new();
+        for (i, text) in uci_moves.iter().enumerate() {
+            let mv = match position.parse_uci(text) {
+                Some(mv) => mv,
+                None => break,
+            };
+            if position.turn == WHITE {
+                words.push(format!("{}.", position.fullmove));
+            } else if i == 0 {
+                words.push(format!("{}...", position.fullmove));
+            }
+            words.push(position.move_to_san(&mv));
+            position.make_move(mv);
+        }
+        words.join(" ")
+    }
+
+    pub fn status(&self) -> Status {
+        let check = self.in_check(self.turn);
+        let has_moves = !self.legal_moves().is_empty();
+        match (check, has_moves) {
+            (true, true) => Status::Check,
+            (true, false) => Status::Checkmate,
+            (false, false) => Status::Stalemate,
+            (false, true) => Status::InProgress,
+        }
+    }
+
+    /// Checks that a hand-made position can be played from.
+    pub fn validate(&self) -> Result<(), String> {
+        for colour in [WHITE, BLACK].iter() {
+            let kings = self.board.iter().filter(|square| **square == [*colour, KING]).count();
+            if kings != 1 {
+                return Err(format!("{} must have exactly one king", colour_name(*colour)));
+            }
+        }
+
+        for file in 0..8 {
+            if self.board[file][1] == PAWN || self.board[56 + file][1] == PAWN {
+                return Err("Pawns cannot stand on the first or last rank".to_string());
+            }
+        }
+
+        if self.in_check(opponent(self.turn)) {
+            return Err(format!("{} is in check but it is not their move", colour_name(opponent(self.turn))));
+        }
+
+        for side in 0..4 {
+            if let Some(file) = self.castling[side] {
+                let colour = if side < 2 { WHITE } else { BLACK };
+                let back_rank: u8 = if colour == WHITE { 0 } else { 7 };
+                let king_home = self.king_square(colour).is_some_and(|sq| sq / 8 == back_rank);
+                if !king_home || self.board[(back_rank * 8 + file) as usize] != [colour, ROOK] {
+                    return Err(format!("{} cannot castle {}", colour_name(colour), ["K", "Q", "k", "q"][side]));
+                }
+            }
+        }
+
+        if let Some(sq) = self.en_passant {
+            let (rank, pawn) = if self.turn == WHITE { (5, sq.wrapping_sub(8)) } else { (2, sq + 8) };
+            if sq / 8 != rank || self.board[sq as usize][1] != 0 || self.board[pawn as usize] != [opponent(self.turn), PAWN] {
+                return Err(format!("{} is not a valid en passant square", square_name(sq)));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Small built-in search for hints when no UCI engine is configured. It is a
+//! plain alpha-beta search to a fixed depth over material, pawn advance and
+//! piece centralisation, with captures searched on until the position is quiet.
+
+use crate::bitboard;
+use crate::rules::{self, Move, Position};
+
+/// Depth of a hint search, in plies before the capture search.
+pub const DEFAULT_DEPTH: u32 = 3;
+const MATE: i32 = 100_000;
+const PIECE_VALUES: [i32; 7] = [0, 100, 320, 330, 500, 900, 0];
+
+/// Best move for the side to move, or None if it has no legal move.
+pub fn best_move(position: &Position, depth: u32) -> Option<Move> {
+    let mut best = None;
+    let mut alpha = -MATE - 1;
+    for mv in ordered(position, bitboard::legal_moves(position)) {
+        let mut next = position.clone();
+        next.make_move(mv);
+        let score = -alpha_beta(&next, depth.saturating_sub(1), -MATE - 1, -alpha, 1);
+        if score > alpha {
+            alpha = score;
+            best = Some(mv);
+        }
+    }
+    best
+}
+
+/// Score of `position` for the side to move, within `alpha` and `beta`.
+fn alpha_beta(position: &Position, depth: u32, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+    let moves = bitboard::legal_moves(position);
+    if moves.is_empty() {
+        // Nearer mates score higher.
+        return if position.in_check(position.turn) { -MATE + ply } else { 0 };
+    }
+    if depth == 0 {
+        return quiesce(position, alpha, beta);
+    }
+    for mv in ordered(position, moves) {
+        let mut next = position.clone();
+        next.make_move(mv);
+        let score = -alpha_beta(&next, depth - 1, -beta, -alpha, ply + 1);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// Searches captures only, so a position is not judged in the middle of an exchange.
+fn quiesce(position: &Position, mut alpha: i32, beta: i32) -> i32 {
+    let standing = evaluate(position);
+    if standing >= beta {
+        return beta;
+    }
+    alpha = alpha.max(standing);
+    let captures: Vec<Move> = bitboard::legal_moves(position).into_iter().filter(|mv| position.is_capture(mv)).collect();
+    for mv in ordered(position, captures) {
+        let mut next = position.clone();
+        next.make_move(mv);
+        let score = -quiesce(&next, -beta, -alpha);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// Promotions and captures of the most valuable piece by the least valuable one first.
+fn ordered(position: &Position, mut moves: Vec<Move>) -> Vec<Move> {
+    moves.sort_by_key(|mv| {
+        let victim = if position.is_capture(mv) { PIECE_VALUES[position.board[mv.to as usize][1] as usize].max(100) } else { 0 };
+        let attacker = PIECE_VALUES[position.board[mv.from as usize][1] as usize] / 100;
+        -(PIECE_VALUES[mv.promotion as usize] + victim * 10 - if victim > 0 { attacker } else { 0 })
+    });
+    moves
+}
+
+/// Material and placement for the side to move, in centipawns.
+fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+    for (sq, [colour, piece]) in position.board.iter().enumerate() {
+        if *piece == 0 {
+            continue;
+        }
+        let (file, rank) = ((sq % 8) as i32, (sq / 8) as i32);
+        let advance = if *colour == rules::WHITE { rank } else { 7 - rank };
+        // 0 on the edge to 3 in the centre.
+        let centre = 3 - (2 * file - 7).abs().max((2 * rank - 7).abs()) / 2;
+        let placement = match *piece {
+            rules::PAWN => (advance - 1) * 8 + if (2..=5).contains(&file) { centre * 4 } else { 0 },
+            rules::KNIGHT | rules::BISHOP => centre * 10,
+            rules::QUEEN => centre * 3,
+            _ => 0,
+        };
+        let value = PIECE_VALUES[*piece as usize] + placement;
+        score += if *colour == position.turn { value } else { -value };
+    }
+    score
+}
@@ -0,0 +1,134 @@
+//! User settings kept between launches in `$XDG_CONFIG_HOME/schnack/settings.toml`
+//! (`~/.config` when the variable is unset). Command line flags override the
+//! file for one launch.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+const APP_DIR: &str = "schnack";
+const SETTINGS_FILE: &str = "settings.toml";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// Board drawn from the other side.
+    pub flipped: bool,
+    /// Promotion piece, one of 'q', 'r', 'b' or 'n'.
+    pub promotion: char,
+    /// Theme name.
+    pub theme: String,
+    /// Window width and height.
+    pub window: (f32, f32),
+    /// Length of move animations in milliseconds, 0 to turn them off.
+    pub animation: u64,
+    /// Sound effects turned off.
+    pub muted: bool,
+}
+
+/// The settings file. Keys left out keep their defaults; the window size is
+/// written as in `--window`, e.g. "1020x720".
+#[derive(Deserialize, Serialize)]
+struct SettingsFile {
+    flipped: Option<bool>,
+    promotion: Option<String>,
+    theme: Option<String>,
+    window: Option<String>,
+    animation: Option<u64>,
+    muted: Option<bool>,
+}
+
+impl Settings {
+    pub fn default(window: (f32, f32)) -> Settings {
+        Settings {
+            flipped: false,
+            promotion: 'q',
+            theme: "Classic".to_string(),
+            window,
+            animation: 200,
+            muted: false,
+        }
+    }
+
+    /// Settings file location, if a config directory can be found.
+    pub fn path() -> Option<PathBuf> {
+        let config = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config.join(APP_DIR).join(SETTINGS_FILE))
+    }
+
+    /// Reads the settings file, keeping the defaults for anything missing or invalid.
+    pub fn load(window: (f32, f32)) -> Settings {
+        let mut settings = Settings::default(window);
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return settings,
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return settings,
+        };
+        let file: SettingsFile = match toml::from_str(&text) {
+            Ok(file) => file,
+            Err(error) => {
+                println!("{}: {}", path.display(), error);
+                return settings;
+            }
+        };
+        settings.flipped = file.flipped.unwrap_or(settings.flipped);
+        settings.theme = file.theme.unwrap_or(settings.theme);
+        settings.animation = file.animation.unwrap_or(settings.animation);
+        settings.muted = file.muted.unwrap_or(settings.muted);
+        for (key, value) in [("promotion", file.promotion), ("window", file.window)].iter() {
+            if let Some(value) = value {
+                if let Err(error) = settings.set(key, value) {
+                    println!("{}: {}", path.display(), error);
+                }
+            }
+        }
+        settings
+    }
+
+    /// Sets one setting from its text form, as used in the file and on the command line.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "flipped" => self.flipped = value.parse().map_err(|_| format!("flipped must be true or false, not {}", value))?,
+            "promotion" => match value {
+                "q" | "r" | "b" | "n" => self.promotion = value.chars().next().unwrap(),
+                _ => return Err(format!("promotion must be q, r, b or n, not {}", value)),
+            },
+            "theme" => self.theme = value.to_string(),
+            "window" => {
+                let size: Vec<f32> = value.split('x').filter_map(|n| n.trim().parse().ok()).collect();
+                if size.len() != 2 || size[0] < 100.0 || size[1] < 100.0 {
+                    return Err(format!("window must be WIDTHxHEIGHT, not {}", value));
+                }
+                self.window = (size[0], size[1]);
+            }
+            "animation" => self.animation = value.parse().map_err(|_| format!("animation must be a number of milliseconds, not {}", value))?,
+            "muted" => self.muted = value.parse().map_err(|_| format!("muted must be true or false, not {}", value))?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Settings::path().ok_or("No config directory, set HOME or XDG_CONFIG_HOME".to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|error| format!("Failed to create {}: {}", dir.display(), error))?;
+        }
+        let file = SettingsFile {
+            flipped: Some(self.flipped),
+            promotion: Some(self.promotion.to_string()),
+            theme: Some(self.theme.clone()),
+            window: Some(format!("{}x{}", self.window.0, self.window.1)),
+            animation: Some(self.animation),
+            muted: Some(self.muted),
+        };
+        let text = toml::to_string(&file).map_err(|error| error.to_string())?;
+        fs::write(&path, text).map_err(|error| format!("Failed to write {}: {}", path.display(), error))
+    }
+}
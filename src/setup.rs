@@ -0,0 +1,71 @@
+//! Board editor state used to prepare positions before play starts.
+
+use crate::rules::{self, Position};
+
+/// Pieces shown in each palette row, in drawing order.
+pub const PALETTE_PIECES: [u8; 6] = [rules::KING, rules::QUEEN, rules::ROOK, rules::BISHOP, rules::KNIGHT, rules::PAWN];
+
+pub struct Setup {
+    pub position: Position,
+    /// Piece placed by a board click as `(colour, piece)`, `(0, 0)` erases.
+    pub selected: (u8, u8),
+    /// When set, the next board click chooses the en passant square.
+    pub picking_en_passant: bool,
+    /// Last validation error, shown in the side panel.
+    pub message: String,
+}
+
+impl Setup {
+    pub fn new(position: Position) -> Setup {
+        Setup {
+            position,
+            selected: (rules::WHITE, rules::PAWN),
+            picking_en_passant: false,
+            message: String::new(),
+        }
+    }
+
+    /// Places the selected piece on `sq`, or removes it if the same piece is already there.
+    pub fn place(&mut self, sq: u8) {
+        if self.picking_en_passant {
+            self.position.en_passant = Some(sq);
+            self.picking_en_passant = false;
+            return;
+        }
+
+        let piece = [self.selected.0, self.selected.1];
+        if self.position.board[sq as usize] == piece {
+            self.position.board[sq as usize] = [0, 0];
+        } else {
+            self.position.board[sq as usize] = piece;
+        }
+        self.message.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.position = Position::empty();
+        self.picking_en_passant = false;
+        self.message.clear();
+    }
+
+    pub fn toggle_turn(&mut self) {
+        self.position.turn = rules::opponent(self.position.turn);
+    }
+
+    /// Toggles one of the standard castling rights, see `rules::WHITE_KING_SIDE` etc.
+    pub fn toggle_castling(&mut self, side: usize) {
+        self.position.castling[side] = match self.position.castling[side] {
+            Some(_) => None,
+            None => Some(if side == rules::WHITE_KING_SIDE || side == rules::BLACK_KING_SIDE { 7 } else { 0 }),
+        };
+    }
+
+    /// Validates the edited position and returns it ready to be played from.
+    pub fn validated(&self) -> Result<Position, String> {
+        self.position.validate()?;
+        let mut position = self.position.clone();
+        position.halfmove = 0;
+        position.fullmove = 1;
+        Ok(position)
+    }
+}
@@ -0,0 +1,151 @@
+//! Sound effects. Each effect plays `resources/sounds/<name>.ogg` if it exists
+//! and a short synthesised tone otherwise. Without the `audio` feature nothing
+//! is loaded or played and ggez's audio module is not started. ggez 0.6 still
+//! compiles its audio libraries whenever the `gui` feature is on; only builds
+//! without `gui` leave them out.
+
+#[cfg(feature = "audio")]
+use std::collections::HashMap;
+
+#[cfg(feature = "audio")]
+use ggez::audio::{self, SoundSource};
+use ggez::Context;
+
+const SAMPLE_RATE: u32 = 22050;
+/// Remaining time under which the low time warning sounds, once per side and game.
+pub const LOW_TIME_SECONDS: u64 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Move,
+    Capture,
+    Castle,
+    Check,
+    LowTime,
+    GameEnd,
+}
+
+pub const ALL_SOUNDS: [Sound; 6] = [Sound::Move, Sound::Capture, Sound::Castle, Sound::Check, Sound::LowTime, Sound::GameEnd];
+
+impl Sound {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sound::Move => "move",
+            Sound::Capture => "capture",
+            Sound::Castle => "castle",
+            Sound::Check => "check",
+            Sound::LowTime => "low_time",
+            Sound::GameEnd => "game_end",
+        }
+    }
+
+    /// Sound for a move given in SAN, with the game end taking precedence over check,
+    /// check over capture and capture over castling.
+    pub fn for_move(san: &str, game_over: bool) -> Sound {
+        if game_over || san.ends_with('#') {
+            Sound::GameEnd
+        } else if san.ends_with('+') {
+            Sound::Check
+        } else if san.contains('x') {
+            Sound::Capture
+        } else if san.starts_with('O') {
+            Sound::Castle
+        } else {
+            Sound::Move
+        }
+    }
+
+    /// Notes of the built-in tone as frequency in Hz and length in milliseconds.
+    fn notes(&self) -> &'static [(f32, u32)] {
+        match self {
+            Sound::Move => &[(660.0, 60)],
+            Sound::Capture => &[(440.0, 90)],
+            Sound::Castle => &[(660.0, 50), (880.0, 50)],
+            Sound::Check => &[(990.0, 120)],
+            Sound::LowTime => &[(880.0, 80), (0.0, 60), (880.0, 80)],
+            Sound::GameEnd => &[(523.0, 150), (659.0, 150), (784.0, 250)],
+        }
+    }
+
+    /// The built-in tone as a 16-bit mono WAV file.
+    pub fn tone(&self) -> Vec<u8> {
+        let mut samples: Vec<i16> = Vec::new();
+        for (frequency, millis) in self.notes().iter() {
+            let count = SAMPLE_RATE * millis / 1000;
+            for i in 0..count {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                // Fades out over the note so it does not click.
+                let volume = 0.3 * (1.0 - i as f32 / count as f32);
+                let value = (2.0 * std::f32::consts::PI * frequency * t).sin() * volume;
+                samples.push((value * i16::MAX as f32) as i16);
+            }
+        }
+
+        let data_size = samples.len() as u32 * 2;
+        let mut wav = Vec::with_capacity(44 + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
+
+pub struct Sounds {
+    pub muted: bool,
+    #[cfg(feature = "audio")]
+    sources: HashMap<Sound, audio::Source>,
+}
+
+impl Sounds {
+    #[cfg(feature = "audio")]
+    pub fn new(ctx: &mut Context, muted: bool) -> Sounds {
+        let mut sources = HashMap::new();
+        for sound in ALL_SOUNDS.iter() {
+            let file = format!("/sounds/{}.ogg", sound.name());
+            let source = if ggez::filesystem::exists(ctx, &file) {
+                audio::Source::new(ctx, &file)
+            } else {
+                audio::Source::from_data(ctx, audio::SoundData::from_bytes(&sound.tone()))
+            };
+            match source {
+                Ok(source) => {
+                    sources.insert(*sound, source);
+                }
+                Err(error) => println!("Failed to load sound {}: {}", sound.name(), error),
+            }
+        }
+        Sounds { muted, sources }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn new(_ctx: &mut Context, muted: bool) -> Sounds {
+        Sounds { muted }
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn play(&mut self, sound: Sound) -> () {
+        if self.muted {
+            return;
+        }
+        if let Some(source) = self.sources.get_mut(&sound) {
+            if let Err(error) = source.play_detached() {
+                println!("Failed to play sound {}: {}", sound.name(), error);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn play(&mut self, _sound: Sound) -> () {}
+}
@@ -0,0 +1,136 @@
+//! Board colours and piece sets.
+//!
+//! Themes are built in or read from `.toml` files in the themes folder:
+//!
+//! ```toml
+//! name = "Green"
+//! light = "#eeeed2"
+//! dark = "#769656"
+//! highlight_light = "#f6f669"
+//! highlight_dark = "#baca2b"
+//! pieces = "/"
+//! ```
+//!
+//! `pieces` is a folder under `resources` holding `white_king.png` and the
+//! other eleven sprites. Keys left out keep the values of the classic theme.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Light squares, also used for the information panel.
+    pub light: [u8; 3],
+    pub dark: [u8; 3],
+    /// Legal move markers on light and dark squares.
+    pub highlight_light: [u8; 3],
+    pub highlight_dark: [u8; 3],
+    /// Piece set folder, relative to `resources`.
+    pub pieces: String,
+}
+
+/// Keys of a theme file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    light: Option<String>,
+    dark: Option<String>,
+    highlight_light: Option<String>,
+    highlight_dark: Option<String>,
+    pieces: Option<String>,
+}
+
+/// Parses "#rrggbb".
+fn parse_colour(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl Theme {
+    /// The original board colours.
+    pub fn classic() -> Theme {
+        Theme {
+            name: "Classic".to_string(),
+            light: [228, 196, 108],
+            dark: [188, 140, 76],
+            highlight_light: [255, 210, 90],
+            highlight_dark: [240, 210, 90],
+            pieces: "/".to_string(),
+        }
+    }
+
+    pub fn built_in() -> Vec<Theme> {
+        vec![
+            Theme::classic(),
+            Theme {
+                name: "Green".to_string(),
+                light: [238, 238, 210],
+                dark: [118, 150, 86],
+                highlight_light: [246, 246, 105],
+                highlight_dark: [186, 202, 43],
+                ..Theme::classic()
+            },
+            Theme {
+                name: "Blue".to_string(),
+                light: [222, 227, 230],
+                dark: [140, 162, 173],
+                highlight_light: [155, 199, 0],
+                highlight_dark: [135, 175, 0],
+                ..Theme::classic()
+            },
+            Theme {
+                name: "High contrast".to_string(),
+                light: [255, 255, 255],
+                dark: [70, 110, 170],
+                highlight_light: [255, 0, 255],
+                highlight_dark: [200, 0, 200],
+                ..Theme::classic()
+            },
+        ]
+    }
+
+    /// Reads a theme file, naming the theme after the file if it has no `name`.
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        let file: ThemeFile = toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let colour = |value: Option<String>, default: [u8; 3]| match value {
+            Some(value) => parse_colour(&value).ok_or(format!("{}: bad colour {}", path.display(), value)),
+            None => Ok(default),
+        };
+        let classic = Theme::classic();
+        Ok(Theme {
+            name: file.name.unwrap_or_else(|| path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("Custom").to_string()),
+            light: colour(file.light, classic.light)?,
+            dark: colour(file.dark, classic.dark)?,
+            highlight_light: colour(file.highlight_light, classic.highlight_light)?,
+            highlight_dark: colour(file.highlight_dark, classic.highlight_dark)?,
+            pieces: file.pieces.unwrap_or(classic.pieces),
+        })
+    }
+
+    /// Built-in themes followed by the `.toml` themes in `dir`, sorted by file name.
+    pub fn load_all(dir: &Path) -> Vec<Theme> {
+        let mut themes = Theme::built_in();
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+            Err(_) => return themes,
+        };
+        paths.retain(|path| path.extension().and_then(|ext| ext.to_str()) == Some("toml"));
+        paths.sort();
+        for path in paths {
+            match Theme::load(&path) {
+                Ok(theme) => themes.push(theme),
+                Err(error) => println!("Skipping theme: {}", error),
+            }
+        }
+        themes
+    }
+}
@@ -0,0 +1,331 @@
+//! Engine-vs-engine matches.
+//!
+//! Two UCI engines play a number of games from a list of openings, each
+//! opening twice with colours reversed. Games are appended to a PGN file and
+//! the score is reported with the Elo difference and its 95% error margin.
+//! Matches run headless with `run`, or game by game in the GUI.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::clock::{Clock, TimeControl};
+use crate::pgn::GameRecord;
+use crate::rules::{self, Position, Status};
+use crate::uci::Engine;
+
+/// Games longer than this are drawn.
+const MAX_PLIES: usize = 600;
+/// How often the headless runner checks for engine output.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Wins, draws and losses of the first engine.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+pub struct Tournament {
+    /// Paths of the first and second engine.
+    pub engines: [String; 2],
+    /// Start positions, each played twice.
+    pub openings: Vec<String>,
+    pub games: usize,
+    /// Number of finished games.
+    pub played: usize,
+    pub control: TimeControl,
+    pub score: MatchScore,
+    pub pgn_path: String,
+}
+
+/// Logistic Elo difference for an expected score between 0 and 1.
+fn elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    /// Elo difference and its 95% error margin, unless one engine scored everything.
+    pub fn elo_difference(&self) -> Option<(f64, f64)> {
+        let games = self.games() as f64;
+        let score = self.ratio();
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2)) / games;
+        let error = 1.96 * (variance / games).sqrt();
+        let low = (score - error).max(1e-6);
+        let high = (score + error).min(1.0 - 1e-6);
+        // Adding zero turns -0 into 0, which would print as "-0.0".
+        Some((elo(score) + 0.0, (elo(high) - elo(low)) / 2.0))
+    }
+
+    /// E.g. "+12 -8 =30  54.0%  Elo +27.9 ± 61.5".
+    pub fn to_text(&self) -> String {
+        let elo = match self.elo_difference() {
+            Some((difference, margin)) => format!("Elo {:+.1} ± {:.1}", difference, margin),
+            None => "Elo n/a".to_string(),
+        };
+        format!("+{} -{} ={}  {:.1}%  {}", self.wins, self.losses, self.draws, self.ratio() * 100.0, elo)
+    }
+}
+
+/// Reads one FEN per line; EPD lines with four fields get the move counters added.
+pub fn load_openings(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let mut openings = Vec::new();
+    for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let fen = if fields.len() >= 6 && fields[4].parse::<u32>().is_ok() {
+            fields[..6].join(" ")
+        } else {
+            format!("{} 0 1", fields[..fields.len().min(4)].join(" "))
+        };
+        match Position::from_fen(&fen) {
+            Ok(position) => openings.push(position.to_fen()),
+            Err(error) => println!("Skipping opening {}: {}", line, error),
+        }
+    }
+    if openings.is_empty() {
+        return Err(format!("No openings in {}", path.display()));
+    }
+    Ok(openings)
+}
+
+/// Result of a game that ends by rule even though moves remain: the fifty-move rule,
+/// threefold repetition, bare kings or the ply limit.
+pub fn adjudicate(record: &GameRecord) -> Option<(String, &'static str)> {
+    let position = Position::from_fen(record.current_fen()).ok()?;
+    if position.halfmove >= 100 {
+        return Some(("1/2-1/2".to_string(), "fifty-move rule"));
+    }
+    let key = rules::position_key(record.current_fen());
+    if record.fens.iter().filter(|fen| rules::position_key(fen) == key).count() >= 3 {
+        return Some(("1/2-1/2".to_string(), "threefold repetition"));
+    }
+    if position.board.iter().all(|square| square[1] == 0 || square[1] == rules::KING) {
+        return Some(("1/2-1/2".to_string(), "insufficient material"));
+    }
+    if record.moves.len() >= MAX_PLIES {
+        return Some(("1/2-1/2".to_string(), "move limit"));
+    }
+    None
+}
+
+impl Tournament {
+    pub fn new(engines: [String; 2], openings: Vec<String>, games: usize, control: TimeControl, pgn_path: String) -> Tournament {
+        Tournament {
+            engines,
+            openings,
+            games,
+            played: 0,
+            control,
+            score: MatchScore::default(),
+            pgn_path,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.played >= self.games
+    }
+
+    /// Start FEN of the next game and whether the second engine has White.
+    pub fn next_game(&self) -> (String, bool) {
+        let opening = &self.openings[(self.played / 2) % self.openings.len()];
+        (opening.clone(), self.played % 2 == 1)
+    }
+
+    /// PGN headers for the next game.
+    pub fn headers(&self, swapped: bool) -> Vec<(String, String)> {
+        let name = |path: &str| Path::new(path).file_stem().map_or(path.to_string(), |stem| stem.to_string_lossy().to_string());
+        let (white, black) = if swapped { (1, 0) } else { (0, 1) };
+        vec![
+            ("Event".to_string(), "Engine match".to_string()),
+            ("Site".to_string(), "ogronman-chess-gui".to_string()),
+            ("Date".to_string(), "????.??.??".to_string()),
+            ("Round".to_string(), format!("{}", self.played + 1)),
+            ("White".to_string(), name(&self.engines[white])),
+            ("Black".to_string(), name(&self.engines[black])),
+            ("TimeControl".to_string(), format!("{}+{}", self.control.base.as_secs(), self.control.increment.as_secs())),
+        ]
+    }
+
+    /// Counts a finished game and appends it to the PGN file.
+    pub fn record_game(&mut self, record: &GameRecord, swapped: bool) {
+        let result = record.result();
+        let first_won = match result.as_str() {
+            "1-0" => Some(!swapped),
+            "0-1" => Some(swapped),
+            _ => None,
+        };
+        match first_won {
+            Some(true) => self.score.wins += 1,
+            Some(false) => self.score.losses += 1,
+            None => self.score.draws += 1,
+        }
+
+        let pgn = record.to_pgn(&self.headers(swapped), &[]);
+        let written = OpenOptions::new().create(true).append(true).open(&self.pgn_path)
+            .and_then(|mut file| writeln!(file, "{}", pgn));
+        if let Err(error) = written {
+            println!("Failed to write {}: {}", self.pgn_path, error);
+        }
+        self.played += 1;
+        println!("Game {} {}: {}", self.played, result, self.score.to_text());
+    }
+
+    /// Side panel lines.
+    pub fn status_lines(&self) -> Vec<String> {
+        vec![
+            format!("Match game {}/{}", (self.played + 1).min(self.games), self.games),
+            self.score.to_text(),
+        ]
+    }
+
+    /// Plays the whole match without the GUI.
+    pub fn run(&mut self) -> Result<(), String> {
+        let mut engines = Vec::new();
+        for path in self.engines.iter() {
+            engines.push(Engine::start(path).map_err(|error| format!("Failed to start engine {}: {}", path, error))?);
+        }
+        while !self.finished() {
+            let (fen, swapped) = self.next_game();
+            if swapped {
+                engines.swap(0, 1);
+            }
+            let record = play_game(&mut engines, &fen, self.control)?;
+            if swapped {
+                engines.swap(0, 1);
+            }
+            self.record_game(&record, swapped);
+        }
+        println!("Final score of {} vs {}: {}", self.engines[0], self.engines[1], self.score.to_text());
+        Ok(())
+    }
+}
+
+/// Plays one game between `engines[0]` as White and `engines[1]` as Black.
+fn play_game(engines: &mut [Engine], fen: &str, control: TimeControl) -> Result<GameRecord, String> {
+    let mut position = Position::from_fen(fen)?;
+    let mut record = GameRecord::new(position.to_fen());
+    let mut clock = Clock::new(control);
+    for engine in engines.iter_mut() {
+        engine.send("ucinewgame").map_err(|error| error.to_string())?;
+    }
+
+    loop {
+        match position.status() {
+            Status::Checkmate | Status::Stalemate => return Ok(record),
+            _ => {}
+        }
+        if let Some((result, reason)) = adjudicate(&record) {
+            println!("Draw by {}", reason);
+            record.variant_result = Some(result);
+            return Ok(record);
+        }
+
+        let index = if position.turn == rules::WHITE { 0 } else { 1 };
+        clock.switch(position.turn);
+        let limits = format!(
+            "wtime {} btime {} winc {} binc {}",
+            clock.remaining(rules::WHITE).as_millis(),
+            clock.remaining(rules::BLACK).as_millis(),
+            control.increment.as_millis(),
+            control.increment.as_millis(),
+        );
+        let engine = &mut engines[index];
+        engine.go(&position.to_fen(), position.chess960, &limits).map_err(|error| error.to_string())?;
+
+        let best_move = loop {
+            engine.poll();
+            if let Some(best_move) = engine.best_move.take() {
+                break Some(best_move);
+            }
+            if clock.flagged().is_some() {
+                let _ = engine.stop();
+                break None;
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        let loses = if position.turn == rules::WHITE { "0-1" } else { "1-0" };
+        let mv = match best_move.and_then(|text| position.parse_uci(&text)) {
+            Some(mv) if clock.flagged().is_none() => mv,
+            Some(_) | None => {
+                println!("{} forfeits: {}", rules::colour_name(position.turn),
+                    if clock.flagged().is_some() { "out of time" } else { "illegal move" });
+                record.variant_result = Some(loses.to_string());
+                return Ok(record);
+            }
+        };
+        let san = position.move_to_san(&mv);
+        position.make_move(mv);
+        record.push(san, position.to_fen());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(start_fen: &str, moves: &[&str]) -> GameRecord {
+        let mut position = Position::from_fen(start_fen).unwrap();
+        let mut record = GameRecord::new(position.to_fen());
+        for uci in moves.iter() {
+            let mv = position.parse_uci(uci).unwrap();
+            let san = position.move_to_san(&mv);
+            position.make_move(mv);
+            record.push(san, position.to_fen());
+        }
+        record
+    }
+
+    #[test]
+    fn elo_difference_and_margin_of_a_known_score() {
+        let score = MatchScore { wins: 12, draws: 30, losses: 8 };
+        let (difference, margin) = score.elo_difference().unwrap();
+        assert!((difference - 27.85).abs() < 0.01);
+        assert!((margin - 61.45).abs() < 0.01);
+        assert_eq!(score.to_text(), "+12 -8 =30  54.0%  Elo +27.9 ± 61.5");
+
+        let even = MatchScore { wins: 5, draws: 10, losses: 5 };
+        assert_eq!(even.to_text(), "+5 -5 =10  50.0%  Elo +0.0 ± 111.3");
+    }
+
+    #[test]
+    fn no_elo_when_one_engine_scored_everything() {
+        assert_eq!(MatchScore { wins: 10, draws: 0, losses: 0 }.elo_difference(), None);
+        assert_eq!(MatchScore { wins: 0, draws: 0, losses: 4 }.elo_difference(), None);
+        assert_eq!(MatchScore::default().elo_difference(), None);
+        assert_eq!(MatchScore { wins: 10, draws: 0, losses: 0 }.to_text(), "+10 -0 =0  100.0%  Elo n/a");
+    }
+
+    #[test]
+    fn adjudicates_draws_by_rule() {
+        assert_eq!(adjudicate(&record(rules::START_FEN, &["e2e4", "e7e5"])), None);
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"];
+        assert_eq!(adjudicate(&record(rules::START_FEN, &shuffle[..4])), None);
+        assert_eq!(adjudicate(&record(rules::START_FEN, &shuffle)), Some(("1/2-1/2".to_string(), "threefold repetition")));
+
+        let fifty = record("4k3/8/8/8/8/8/8/R3K3 w - - 99 80", &["a1a2"]);
+        assert_eq!(adjudicate(&fifty), Some(("1/2-1/2".to_string(), "fifty-move rule")));
+
+        let bare_kings = record("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1", &["e1d2"]);
+        assert_eq!(adjudicate(&bare_kings), Some(("1/2-1/2".to_string(), "insufficient material")));
+    }
+}
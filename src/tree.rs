@@ -0,0 +1,354 @@
+//! Move tree of a game with its variations. Playing a move that differs from
+//! the one already played from a position starts a new variation; the first
+//! child of every node is the main line. Nodes keep their comment and NAGs,
+//! and the tree reads and writes PGN with nested variations.
+
+use crate::pgn::{self, GameRecord, PgnGame, Token};
+use crate::rules::{self, Position};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    /// Move leading to this node in SAN, empty for the root.
+    pub san: String,
+    /// Position after the move.
+    pub fen: String,
+    pub parent: Option<usize>,
+    /// Continuations, the main one first.
+    pub children: Vec<usize>,
+    /// Comment after the move, or before the first move for the root.
+    pub comment: String,
+    pub nags: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveTree {
+    /// Node 0 is the start position. Deleted variations stay here but are unreachable.
+    pub nodes: Vec<Node>,
+    pub current: usize,
+}
+
+/// Symbol of a move assessment NAG, e.g. "!?" for 5.
+pub fn nag_symbol(nag: u8) -> Option<&'static str> {
+    match nag {
+        1 => Some("!"),
+        2 => Some("?"),
+        3 => Some("!!"),
+        4 => Some("??"),
+        5 => Some("!?"),
+        6 => Some("?!"),
+        _ => None,
+    }
+}
+
+impl MoveTree {
+    pub fn new(start_fen: String) -> MoveTree {
+        MoveTree {
+            nodes: vec![Node { san: String::new(), fen: start_fen, parent: None, children: Vec::new(), comment: String::new(), nags: Vec::new() }],
+            current: 0,
+        }
+    }
+
+    pub fn start_fen(&self) -> &str {
+        &self.nodes[0].fen
+    }
+
+    /// Plays `san` from the current node, following the existing move if it was played before
+    /// and starting a new variation otherwise.
+    pub fn play(&mut self, san: &str, fen: &str) -> usize {
+        let existing = self.nodes[self.current].children.iter().cloned().find(|child| self.nodes[*child].san == san);
+        self.current = match existing {
+            Some(child) => child,
+            None => {
+                let node = self.nodes.len();
+                self.nodes.push(Node {
+                    san: san.to_string(),
+                    fen: fen.to_string(),
+                    parent: Some(self.current),
+                    children: Vec::new(),
+                    comment: String::new(),
+                    nags: Vec::new(),
+                });
+                self.nodes[self.current].children.push(node);
+                node
+            }
+        };
+        self.current
+    }
+
+    /// Nodes from the first move to `node`.
+    pub fn path(&self, node: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut current = node;
+        while let Some(parent) = self.nodes[current].parent {
+            path.push(current);
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The moves and positions from the start to `node`.
+    pub fn record(&self, node: usize) -> GameRecord {
+        let mut record = GameRecord::new(self.start_fen().to_string());
+        for step in self.path(node) {
+            record.push(self.nodes[step].san.clone(), self.nodes[step].fen.clone());
+        }
+        record
+    }
+
+    /// Last node of the line continuing from `node` along main moves.
+    pub fn line_end(&self, node: usize) -> usize {
+        let mut current = node;
+        while let Some(child) = self.nodes[current].children.first() {
+            current = *child;
+        }
+        current
+    }
+
+    /// The node before the current one.
+    pub fn back(&self) -> Option<usize> {
+        self.nodes[self.current].parent
+    }
+
+    /// The main continuation of the current node.
+    pub fn forward(&self) -> Option<usize> {
+        self.nodes[self.current].children.first().cloned()
+    }
+
+    /// The alternative to the current move `offset` places along among its siblings.
+    pub fn sibling(&self, offset: isize) -> Option<usize> {
+        let siblings = &self.nodes[self.nodes[self.current].parent?].children;
+        let index = siblings.iter().position(|child| *child == self.current)? as isize + offset;
+        if index < 0 { None } else { siblings.get(index as usize).cloned() }
+    }
+
+    /// First move of the variation `node` is in, or None on the main line.
+    fn variation_start(&self, node: usize) -> Option<usize> {
+        let mut current = node;
+        while let Some(parent) = self.nodes[current].parent {
+            if self.nodes[parent].children[0] != current {
+                return Some(current);
+            }
+            current = parent;
+        }
+        None
+    }
+
+    /// Makes the variation containing the current move the main continuation at its branch point.
+    pub fn promote(&mut self) -> bool {
+        let start = match self.variation_start(self.current) {
+            Some(start) => start,
+            None => return false,
+        };
+        let parent = self.nodes[start].parent.unwrap();
+        let children = &mut self.nodes[parent].children;
+        children.retain(|child| *child != start);
+        children.insert(0, start);
+        true
+    }
+
+    /// Deletes the variation containing the current move, or on the main line the current move
+    /// and everything after it. The current node becomes the one before the deleted moves.
+    pub fn delete(&mut self) -> bool {
+        let start = self.variation_start(self.current).unwrap_or(self.current);
+        let parent = match self.nodes[start].parent {
+            Some(parent) => parent,
+            None => return false,
+        };
+        self.nodes[parent].children.retain(|child| *child != start);
+        self.current = parent;
+        true
+    }
+
+    /// Adds or removes a move assessment NAG (1 to 6) on the current move, replacing any other.
+    pub fn toggle_nag(&mut self, nag: u8) {
+        if self.current == 0 {
+            return;
+        }
+        let nags = &mut self.nodes[self.current].nags;
+        let had = nags.contains(&nag);
+        nags.retain(|existing| nag_symbol(*existing).is_none());
+        if !had {
+            nags.insert(0, nag);
+        }
+    }
+
+    /// Reads the moves, variations, comments and NAGs of a PGN game.
+    pub fn from_pgn(game: &PgnGame) -> Result<MoveTree, String> {
+        let mut tree = MoveTree::new(Position::from_fen(&game.start_fen())?.to_fen());
+        // Node each open variation returns to.
+        let mut stack = Vec::new();
+        for token in game.tokens.iter() {
+            match token {
+                Token::Move(san) => {
+                    let position = Position::from_fen(&tree.nodes[tree.current].fen)?;
+                    let mv = position.parse_san(san).ok_or(format!("Illegal move {} in {}", san, position.to_fen()))?;
+                    let mut next = position.clone();
+                    next.make_move(mv);
+                    tree.play(&position.move_to_san(&mv), &next.to_fen());
+                }
+                Token::Comment(comment) => {
+                    let text = &mut tree.nodes[tree.current].comment;
+                    if !text.is_empty() {
+                        text.push(' ');
+                    }
+                    text.push_str(comment);
+                }
+                Token::Nag(nag) => tree.nodes[tree.current].nags.push(*nag),
+                Token::Open => {
+                    stack.push(tree.current);
+                    tree.current = tree.nodes[tree.current].parent.ok_or("Variation before the first move".to_string())?;
+                }
+                Token::Close => tree.current = stack.pop().ok_or("Unbalanced parentheses".to_string())?,
+                Token::Result(_) => {}
+            }
+        }
+        tree.current = 0;
+        Ok(tree)
+    }
+
+    /// Movetext words for the continuation of `node` and its variations.
+    fn write_line(&self, node: usize, mut force_number: bool, symbols: bool, words: &mut Vec<String>) {
+        let mut current = node;
+        while let Some(&main) = self.nodes[current].children.first() {
+            self.write_move(main, force_number, symbols, words);
+            for &variation in self.nodes[current].children[1..].iter() {
+                let first = words.len();
+                self.write_move(variation, true, symbols, words);
+                self.write_line(variation, !self.nodes[variation].comment.is_empty(), symbols, words);
+                words[first].insert(0, '(');
+                words.last_mut().unwrap().push(')');
+            }
+            force_number = self.nodes[current].children.len() > 1 || !self.nodes[main].comment.is_empty();
+            current = main;
+        }
+    }
+
+    /// Number, SAN, NAGs and comment of the move leading to `node`. With `symbols` NAGs are
+    /// written as "!" or "?!" where possible and the current move is put in brackets.
+    fn write_move(&self, node: usize, force_number: bool, symbols: bool, words: &mut Vec<String>) {
+        let before = Position::from_fen(&self.nodes[self.nodes[node].parent.unwrap()].fen).unwrap_or(Position::start());
+        if before.turn == rules::WHITE {
+            words.push(format!("{}.", before.fullmove));
+        } else if force_number {
+            words.push(format!("{}...", before.fullmove));
+        }
+        let mut san = self.nodes[node].san.clone();
+        for nag in self.nodes[node].nags.iter() {
+            match nag_symbol(*nag) {
+                Some(symbol) if symbols => san.push_str(symbol),
+                _ => {
+                    words.push(san);
+                    san = format!("${}", nag);
+                }
+            }
+        }
+        if symbols && node == self.current {
+            san = format!("[{}]", san);
+        }
+        words.push(san);
+        if !self.nodes[node].comment.is_empty() {
+            words.push(format!("{{{}}}", self.nodes[node].comment));
+        }
+    }
+
+    /// The whole tree as movetext words, with `extra(fen)` added to each comment.
+    fn words(&self, symbols: bool, extra: &dyn Fn(&str) -> String) -> Vec<String> {
+        let mut tree = self.clone();
+        for node in tree.nodes.iter_mut() {
+            let added = extra(&node.fen);
+            if !added.is_empty() {
+                node.comment = if node.comment.is_empty() { added } else { format!("{} {}", node.comment, added) };
+            }
+        }
+        let mut words = Vec::new();
+        if !tree.nodes[0].comment.is_empty() {
+            words.push(format!("{{{}}}", tree.nodes[0].comment));
+        }
+        tree.write_line(0, true, symbols, &mut words);
+        words
+    }
+
+    /// PGN of the whole tree. `extra(fen)` gives text to add to the comment after each
+    /// position, such as its drawn arrows.
+    pub fn to_pgn(&self, headers: &[(String, String)], result: &str, extra: &dyn Fn(&str) -> String) -> String {
+        pgn::write_pgn(headers, self.start_fen(), result, self.words(false, extra))
+    }
+
+    /// Movetext for the side panel, with NAG symbols and the current move in brackets.
+    pub fn display_text(&self) -> String {
+        self.words(true, &|_| String::new()).join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = "[Event \"Variations\"]\n\n\
+        {Start} 1. e4 {King's pawn} e5 (1... c5 $14 2. Nf3 (2. c3 d5) d6) 2. Nf3!? Nc6 $1 *\n";
+
+    fn parse(text: &str) -> MoveTree {
+        MoveTree::from_pgn(&pgn::parse_games(text)[0]).unwrap()
+    }
+
+    /// Node reached by following `moves` from the start.
+    fn find(tree: &MoveTree, moves: &[&str]) -> usize {
+        moves.iter().fold(0, |node, san| {
+            *tree.nodes[node].children.iter().find(|child| tree.nodes[**child].san == *san).unwrap()
+        })
+    }
+
+    #[test]
+    fn reads_variations_comments_and_nags() {
+        let tree = parse(GAME);
+        assert_eq!(tree.nodes[0].comment, "Start");
+        let e4 = find(&tree, &["e4"]);
+        assert_eq!(tree.nodes[e4].comment, "King's pawn");
+        let sans: Vec<&str> = tree.nodes[e4].children.iter().map(|child| tree.nodes[*child].san.as_str()).collect();
+        assert_eq!(sans, vec!["e5", "c5"]);
+        assert_eq!(tree.nodes[find(&tree, &["e4", "c5"])].nags, vec![14]);
+        assert_eq!(tree.nodes[find(&tree, &["e4", "e5", "Nf3"])].nags, vec![5]);
+        assert_eq!(tree.nodes[find(&tree, &["e4", "e5", "Nf3", "Nc6"])].nags, vec![1]);
+        find(&tree, &["e4", "c5", "c3", "d5"]);
+    }
+
+    #[test]
+    fn pgn_round_trip_keeps_the_tree() {
+        let tree = parse(GAME);
+        let text = tree.to_pgn(&[("Event".to_string(), "Variations".to_string())], "*", &|_| String::new());
+        assert!(text.contains("(1... c5 $14 2. Nf3 (2. c3 d5) 2... d6)"), "{}", text);
+        assert_eq!(parse(&text), tree);
+    }
+
+    #[test]
+    fn promotes_and_deletes_variations() {
+        let mut tree = parse(GAME);
+        let e4 = find(&tree, &["e4"]);
+        let c5 = find(&tree, &["e4", "c5"]);
+        tree.current = find(&tree, &["e4", "c5", "Nf3", "d6"]);
+        assert!(tree.promote());
+        assert_eq!(tree.nodes[e4].children[0], c5);
+        assert!(!tree.promote());
+
+        let nf3 = find(&tree, &["e4", "c5", "Nf3"]);
+        tree.current = find(&tree, &["e4", "c5", "c3", "d5"]);
+        assert!(tree.delete());
+        assert_eq!(tree.current, c5);
+        assert_eq!(tree.nodes[c5].children, vec![nf3]);
+    }
+
+    #[test]
+    fn toggles_assessment_nags() {
+        let mut tree = parse(GAME);
+        tree.current = find(&tree, &["e4", "e5", "Nf3"]);
+        tree.toggle_nag(1);
+        assert_eq!(tree.nodes[tree.current].nags, vec![1]);
+        tree.toggle_nag(1);
+        assert!(tree.nodes[tree.current].nags.is_empty());
+
+        tree.current = find(&tree, &["e4", "c5"]);
+        tree.toggle_nag(2);
+        assert_eq!(tree.nodes[tree.current].nags, vec![2, 14]);
+    }
+}
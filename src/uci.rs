@@ -0,0 +1,251 @@
+//! Minimal UCI engine client. The engine runs as a child process and its
+//! output is read on a background thread so the GUI never blocks on it.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Engine score from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    pub depth: u32,
+    pub score: Option<Score>,
+    /// Principal variation in UCI notation.
+    pub pv: Vec<String>,
+}
+
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    searching: bool,
+    /// `bestmove` replies still owed by searches that were stopped, whose output is ignored.
+    stale_searches: u32,
+    pub analysis: Analysis,
+    /// Move from the last finished search, in UCI notation.
+    pub best_move: Option<String>,
+}
+
+impl Score {
+    /// Flips the score to White's point of view when Black is to move.
+    pub fn for_white(&self, white_to_move: bool) -> Score {
+        if white_to_move {
+            return *self;
+        }
+        match *self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(moves) => Score::Mate(-moves),
+        }
+    }
+
+    /// Expected share of the points for the side the score belongs to, between 0 and 1, as
+    /// Lichess fits it to game results.
+    pub fn winning_chance(&self) -> f32 {
+        match *self {
+            Score::Centipawns(cp) => 1.0 / (1.0 + (-0.00368208 * cp as f32).exp()),
+            Score::Mate(moves) => if moves > 0 { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Text such as "+0.35" or "#-3".
+    pub fn to_text(&self) -> String {
+        match *self {
+            Score::Centipawns(cp) => format!("{:+.2}", cp as f32 / 100.0),
+            Score::Mate(moves) => format!("#{}", moves),
+        }
+    }
+}
+
+/// Updates `analysis` from an `info` line. Returns false if the line carried no search result.
+pub fn parse_info(line: &str, analysis: &mut Analysis) -> bool {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut updated = false;
+    let mut i = 1;
+    while i < tokens.len() {
+        match tokens[i] {
+            "multipv" if tokens.get(i + 1) != Some(&"1") => return false,
+            // Free text to the end of the line, which may contain any of the keywords.
+            "string" => break,
+            "depth" => {
+                if let Some(depth) = tokens.get(i + 1).and_then(|t| t.parse().ok()) {
+                    analysis.depth = depth;
+                }
+                i += 1;
+            }
+            "score" => {
+                let value = tokens.get(i + 2).and_then(|t| t.parse().ok());
+                match (tokens.get(i + 1), value) {
+                    (Some(&"cp"), Some(cp)) => analysis.score = Some(Score::Centipawns(cp)),
+                    (Some(&"mate"), Some(moves)) => analysis.score = Some(Score::Mate(moves)),
+                    _ => {}
+                }
+                updated = true;
+                i += 2;
+            }
+            "pv" => {
+                analysis.pv = tokens[i + 1..].iter().map(|t| t.to_string()).collect();
+                updated = true;
+                break;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    updated
+}
+
+impl Engine {
+    /// Launches the engine at `path` and starts the UCI handshake.
+    pub fn start(path: &str) -> io::Result<Engine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break; },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = Engine {
+            child,
+            stdin,
+            lines,
+            searching: false,
+            stale_searches: 0,
+            analysis: Analysis::default(),
+            best_move: None,
+        };
+        engine.send("uci")?;
+        engine.send("isready")?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Restarts an infinite search on `fen`.
+    pub fn analyse(&mut self, fen: &str, chess960: bool) -> io::Result<()> {
+        self.go(fen, chess960, "infinite")
+    }
+
+    /// Starts a search on `fen` with `limits` such as "movetime 1000", stopping any running one.
+    /// Chess960 positions need the engine's `UCI_Chess960` option; engines without it ignore the setting.
+    pub fn go(&mut self, fen: &str, chess960: bool, limits: &str) -> io::Result<()> {
+        self.stop()?;
+        self.analysis = Analysis::default();
+        self.best_move = None;
+        self.send(&format!("setoption name UCI_Chess960 value {}", chess960))?;
+        self.send(&format!("position fen {}", fen))?;
+        self.send(&format!("go {}", limits))?;
+        self.searching = true;
+        Ok(())
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    /// Stops the running search. Its reply is ignored unless it has already arrived.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.poll();
+        if self.searching {
+            self.send("stop")?;
+            self.searching = false;
+            self.stale_searches += 1;
+        }
+        Ok(())
+    }
+
+    /// Reads pending engine output into `analysis`. Returns true if it changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(line) = self.lines.try_recv() {
+            if line.starts_with("bestmove") {
+                if self.stale_searches > 0 {
+                    self.stale_searches -= 1;
+                } else {
+                    self.searching = false;
+                    self.best_move = line.split_whitespace().nth(1).filter(|mv| *mv != "(none)").map(|mv| mv.to_string());
+                }
+            } else if line.starts_with("info") && self.stale_searches == 0 {
+                changed |= parse_info(&line, &mut self.analysis);
+            }
+        }
+        changed
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Polls `engine` until its search finishes.
+    fn wait(engine: &mut Engine) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.is_searching() {
+            assert!(Instant::now() < deadline, "the engine did not answer");
+            engine.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn mock_engine_answers_go() {
+        let mut engine = Engine::start("scripts/mock_engine.sh").unwrap();
+        engine.go(START_FEN, false, "depth 1").unwrap();
+        wait(&mut engine);
+        assert_eq!(engine.best_move.as_deref(), Some("e2e4"));
+        assert_eq!(engine.analysis.score, Some(Score::Centipawns(20)));
+        assert_eq!(engine.analysis.pv, vec!["e2e4"]);
+    }
+
+    #[test]
+    fn stop_keeps_a_reply_that_already_arrived() {
+        let mut engine = Engine::start("scripts/mock_engine.sh").unwrap();
+        engine.go(START_FEN, false, "depth 1").unwrap();
+        // The mock answers at once, so the reply is waiting when the search is stopped.
+        thread::sleep(Duration::from_millis(200));
+        engine.stop().unwrap();
+        assert_eq!(engine.best_move.as_deref(), Some("e2e4"));
+        engine.go(START_FEN, false, "depth 1").unwrap();
+        wait(&mut engine);
+        assert_eq!(engine.best_move.as_deref(), Some("e2e4"));
+    }
+
+    #[test]
+    fn info_string_is_not_a_result() {
+        let mut analysis = Analysis::default();
+        assert!(!parse_info("info string NNUE evaluation using pv e2e4", &mut analysis));
+        assert!(analysis.pv.is_empty());
+        assert!(parse_info("info depth 5 score mate -3 pv e7e5", &mut analysis));
+        assert_eq!((analysis.depth, analysis.score), (5, Some(Score::Mate(-3))));
+    }
+}
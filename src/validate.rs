@@ -0,0 +1,140 @@
+//! Cross-checks a rules backend against the bitboard generator. With
+//! `VALIDATE_MOVES` set the GUI runs it after every move of a standard game,
+//! so a wrong highlight or a refused legal move can be traced to the backend,
+//! with a bug report to send to its authors.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::backend::ChessBackend;
+use crate::bitboard;
+use crate::rules::{self, Position};
+
+/// A square where the backend and the reference disagree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discrepancy {
+    pub square: String,
+    /// Legal targets the backend does not offer.
+    pub missing: Vec<String>,
+    /// Targets the backend offers that are not legal.
+    pub extra: Vec<String>,
+}
+
+/// Compares `get_possible_moves` for every piece of the side to move with the bitboard generator.
+pub fn compare(backend: &dyn ChessBackend) -> Result<Vec<Discrepancy>, String> {
+    let position = Position::from_fen(&backend.get_fen())?;
+    let legal = bitboard::legal_moves(&position);
+    let mut discrepancies = Vec::new();
+    for sq in (0..64u8).filter(|sq| position.board[*sq as usize][0] == position.turn) {
+        let square = rules::square_name(sq);
+        let mut expected: Vec<String> = legal.iter().filter(|mv| mv.from == sq).map(|mv| rules::square_name(mv.to)).collect();
+        let mut actual = backend.get_possible_moves(&square);
+        expected.sort();
+        expected.dedup();
+        actual.sort();
+        actual.dedup();
+        if expected != actual {
+            discrepancies.push(Discrepancy {
+                square,
+                missing: expected.iter().filter(|to| !actual.contains(to)).cloned().collect(),
+                extra: actual.iter().filter(|to| !expected.contains(to)).cloned().collect(),
+            });
+        }
+    }
+    Ok(discrepancies)
+}
+
+/// Bug report text with the moves leading to the position, so it can be reproduced.
+pub fn report(backend: &str, fen: &str, moves: &[String], discrepancies: &[Discrepancy]) -> String {
+    let mut text = format!("Move generation mismatch in the {} backend\n\nFEN: {}\n", backend, fen);
+    if !moves.is_empty() {
+        text.push_str(&format!("Moves from the start position: {}\n", moves.join(" ")));
+    }
+    text.push('\n');
+    for discrepancy in discrepancies.iter() {
+        let mut problems = Vec::new();
+        if !discrepancy.missing.is_empty() {
+            problems.push(format!("legal but not offered: {}", discrepancy.missing.join(" ")));
+        }
+        if !discrepancy.extra.is_empty() {
+            problems.push(format!("offered but illegal: {}", discrepancy.extra.join(" ")));
+        }
+        text.push_str(&format!("{}: {}\n", discrepancy.square, problems.join("; ")));
+    }
+    text
+}
+
+/// Writes `text` to a new `movegen-bug-<seconds>.txt` in `dir`.
+pub fn write_report(dir: &Path, text: &str) -> Result<PathBuf, String> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let mut path = dir.join(format!("movegen-bug-{}.txt", seconds));
+    let mut number = 1;
+    while path.exists() {
+        number += 1;
+        path = dir.join(format!("movegen-bug-{}-{}.txt", seconds, number));
+    }
+    fs::write(&path, text).map_err(|error| format!("Failed to write {}: {}", path.display(), error))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RulesBackend;
+
+    /// The rules backend with a pawn that cannot advance two squares and a knight that can
+    /// go to g2, like an upstream generator with bugs.
+    struct Broken(RulesBackend);
+
+    impl ChessBackend for Broken {
+        fn make_move(&mut self, from: &str, to: &str) -> bool {
+            self.0.make_move(from, to)
+        }
+
+        fn get_possible_moves(&self, square: &str) -> Vec<String> {
+            let mut moves = self.0.get_possible_moves(square);
+            match square {
+                "e2" => moves.retain(|to| to != "e4"),
+                "g1" => moves.push("g2".to_string()),
+                _ => {}
+            }
+            moves
+        }
+
+        fn set_promotion(&mut self, square: &str, piece: u8) {
+            self.0.set_promotion(square, piece)
+        }
+
+        fn get_fen(&self) -> String {
+            self.0.get_fen()
+        }
+
+        fn get_game_state(&self) -> String {
+            self.0.get_game_state()
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_rules_backend() {
+        assert_eq!(compare(&RulesBackend::new(Position::legal_moves)), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn reports_missing_and_extra_moves() {
+        let mut backend = Broken(RulesBackend::new(Position::legal_moves));
+        assert!(backend.make_move("b1", "c3") && backend.make_move("b8", "c6"));
+        let discrepancies = compare(&backend).unwrap();
+        assert_eq!(discrepancies, vec![
+            Discrepancy { square: "g1".to_string(), missing: Vec::new(), extra: vec!["g2".to_string()] },
+            Discrepancy { square: "e2".to_string(), missing: vec!["e4".to_string()], extra: Vec::new() },
+        ]);
+
+        let fen = backend.get_fen();
+        let text = report("broken", &fen, &["Nc3".to_string(), "Nc6".to_string()], &discrepancies);
+        assert_eq!(text, format!("Move generation mismatch in the broken backend\n\nFEN: {}\n\
+            Moves from the start position: Nc3 Nc6\n\n\
+            g1: offered but illegal: g2\n\
+            e2: legal but not offered: e4\n", fen));
+    }
+}
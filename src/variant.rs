@@ -0,0 +1,327 @@
+//! Variant rules on top of `rules::Position`.
+//!
+//! King of the Hill is won by bringing the king to one of the four centre
+//! squares, Three-check by giving the third check. In Atomic every capture
+//! explodes: the capturing piece and all pieces but pawns next to the target
+//! square are removed, and the side whose king explodes loses.
+
+use crate::rules::{self, Move, Position, Status};
+
+/// d4, e4, d5 and e5.
+const HILL: [u8; 4] = [27, 28, 35, 36];
+const CHECKS_TO_WIN: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Atomic,
+}
+
+/// A game under variant rules. Used for every game the `ChessBackend` cannot play: variants,
+/// Chess960 and games from an edited position.
+#[derive(Clone, Debug)]
+pub struct VariantGame {
+    pub variant: Variant,
+    pub position: Position,
+    /// Checks given by White and by Black.
+    pub checks: [u32; 2],
+}
+
+impl Variant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Atomic => "Atomic",
+        }
+    }
+
+    /// The variant after this one, for cycling through them with a key.
+    pub fn next(&self) -> Variant {
+        match self {
+            Variant::Standard => Variant::KingOfTheHill,
+            Variant::KingOfTheHill => Variant::ThreeCheck,
+            Variant::ThreeCheck => Variant::Atomic,
+            Variant::Atomic => Variant::Standard,
+        }
+    }
+}
+
+fn colour_index(colour: u8) -> usize {
+    if colour == rules::WHITE { 0 } else { 1 }
+}
+
+impl VariantGame {
+    pub fn new(variant: Variant, position: Position) -> VariantGame {
+        VariantGame {
+            variant,
+            position,
+            checks: [0, 0],
+        }
+    }
+
+    /// Whether `colour` is in check. In Atomic a king next to the enemy king cannot be checked,
+    /// as capturing it would blow up both kings.
+    fn in_check(position: &Position, variant: Variant, colour: u8) -> bool {
+        if variant == Variant::Atomic {
+            if let (Some(king), Some(other)) = (position.king_square(colour), position.king_square(rules::opponent(colour))) {
+                if rules::KING_STEPS.iter().any(|(df, dr)| rules::offset(king, *df, *dr) == Some(other)) {
+                    return false;
+                }
+            }
+        }
+        position.in_check(colour)
+    }
+
+    /// Plays `mv` on `position`, exploding captures in Atomic.
+    fn apply(position: &mut Position, variant: Variant, mv: Move) {
+        let capture = position.is_capture(&mv) && position.castling_side(&mv).is_none();
+        position.make_move(mv);
+        if variant != Variant::Atomic || !capture {
+            return;
+        }
+
+        position.board[mv.to as usize] = [0, 0];
+        for (df, dr) in rules::KING_STEPS.iter() {
+            if let Some(sq) = rules::offset(mv.to, *df, *dr) {
+                if position.board[sq as usize][1] != rules::PAWN {
+                    position.board[sq as usize] = [0, 0];
+                }
+            }
+        }
+        // Exploded rooks and kings take their castling rights with them.
+        for side in 0..4 {
+            if let Some(file) = position.castling[side] {
+                let colour = if side < 2 { rules::WHITE } else { rules::BLACK };
+                let back_rank = if colour == rules::WHITE { 0 } else { 56 };
+                if position.board[(back_rank + file) as usize] != [colour, rules::ROOK] || position.king_square(colour).is_none() {
+                    position.castling[side] = None;
+                }
+            }
+        }
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.variant_winner().is_some() {
+            return Vec::new();
+        }
+        if self.variant != Variant::Atomic {
+            return self.position.legal_moves();
+        }
+
+        let us = self.position.turn;
+        self.position.pseudo_legal_moves()
+            .into_iter()
+            .filter(|mv| {
+                let castles = self.position.castling_side(mv).is_some();
+                if !castles && self.position.board[mv.from as usize][1] == rules::KING && self.position.is_capture(mv) {
+                    return false;
+                }
+                let mut next = self.position.clone();
+                VariantGame::apply(&mut next, self.variant, *mv);
+                if next.king_square(us).is_none() {
+                    return false;
+                }
+                next.king_square(rules::opponent(us)).is_none() || !VariantGame::in_check(&next, self.variant, us)
+            })
+            .collect()
+    }
+
+    pub fn legal_moves_from(&self, from: u8) -> Vec<Move> {
+        self.legal_moves().into_iter().filter(|mv| mv.from == from).collect()
+    }
+
+    pub fn make_move(&mut self, mv: Move) {
+        let us = self.position.turn;
+        VariantGame::apply(&mut self.position, self.variant, mv);
+        if VariantGame::in_check(&self.position, self.variant, rules::opponent(us)) {
+            self.checks[colour_index(us)] += 1;
+        }
+    }
+
+    /// SAN of a legal move, with `+` or `#` judged by the variant rules.
+    pub fn move_to_san(&self, mv: &Move) -> String {
+        let mut san = self.position.san_without_check(mv, &self.legal_moves());
+        let mut next = self.clone();
+        next.make_move(*mv);
+        if next.winner() == Some(self.position.turn) {
+            san.push('#');
+        } else if VariantGame::in_check(&next.position, self.variant, next.position.turn) {
+            san.push('+');
+        }
+        san
+    }
+
+    /// Winner of a game decided by a variant rule rather than by mate.
+    fn variant_winner(&self) -> Option<(u8, &'static str)> {
+        for colour in [rules::WHITE, rules::BLACK].iter() {
+            let them = rules::opponent(*colour);
+            match self.variant {
+                Variant::KingOfTheHill => {
+                    if self.position.king_square(*colour).is_some_and(|king| HILL.contains(&king)) {
+                        return Some((*colour, "king of the hill"));
+                    }
+                }
+                Variant::ThreeCheck => {
+                    if self.checks[colour_index(*colour)] >= CHECKS_TO_WIN {
+                        return Some((*colour, "three checks"));
+                    }
+                }
+                Variant::Atomic => {
+                    if self.position.king_square(them).is_none() {
+                        return Some((*colour, "explosion"));
+                    }
+                }
+                Variant::Standard => {}
+            }
+        }
+        None
+    }
+
+    /// Winning colour, if the game is decided.
+    pub fn winner(&self) -> Option<u8> {
+        if let Some((colour, _)) = self.variant_winner() {
+            return Some(colour);
+        }
+        match self.status() {
+            Status::Checkmate => Some(rules::opponent(self.position.turn)),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        let check = VariantGame::in_check(&self.position, self.variant, self.position.turn);
+        if self.variant_winner().is_some() {
+            return if check { Status::Check } else { Status::InProgress };
+        }
+        match (check, !self.legal_moves().is_empty()) {
+            (true, true) => Status::Check,
+            (true, false) => Status::Checkmate,
+            (false, false) => Status::Stalemate,
+            (false, true) => Status::InProgress,
+        }
+    }
+
+    /// PGN result token.
+    pub fn result(&self) -> String {
+        match self.winner() {
+            Some(rules::WHITE) => "1-0".to_string(),
+            Some(_) => "0-1".to_string(),
+            None if self.status() == Status::Stalemate => "1/2-1/2".to_string(),
+            None => "*".to_string(),
+        }
+    }
+
+    /// Game state for the status text, e.g. "Check" or "White wins by three checks".
+    pub fn state_text(&self) -> String {
+        match self.variant_winner() {
+            Some((colour, reason)) => format!("{} wins by {}", rules::colour_name(colour), reason),
+            None => format!("{:?}", self.status()),
+        }
+    }
+
+    /// Side panel lines describing the variant.
+    pub fn status_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.variant != Variant::Standard {
+            lines.push(format!("Variant: {}", self.variant.name()));
+        }
+        if self.variant == Variant::ThreeCheck {
+            lines.push(format!("Checks: White {}  Black {}", self.checks[0], self.checks[1]));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(variant: Variant, fen: &str) -> VariantGame {
+        VariantGame::new(variant, Position::from_fen(fen).unwrap())
+    }
+
+    fn play(game: &mut VariantGame, uci: &str) -> String {
+        let mv = game.position.parse_uci(uci).unwrap();
+        assert!(game.legal_moves().contains(&mv), "{} is not legal", uci);
+        let san = game.move_to_san(&mv);
+        game.make_move(mv);
+        san
+    }
+
+    fn piece(game: &VariantGame, square: &str) -> [u8; 2] {
+        game.position.board[rules::parse_square(square).unwrap() as usize]
+    }
+
+    #[test]
+    fn atomic_captures_explode_all_but_pawns() {
+        let mut atomic = game(Variant::Atomic, "k7/8/8/8/2pnb3/8/8/3QK3 w - - 0 1");
+        assert_eq!(play(&mut atomic, "d1d4"), "Qxd4");
+        assert_eq!(piece(&atomic, "d4"), [0, 0]);
+        assert_eq!(piece(&atomic, "e4"), [0, 0]);
+        assert_eq!(piece(&atomic, "c4"), [rules::BLACK, rules::PAWN]);
+        assert_eq!(atomic.winner(), None);
+    }
+
+    #[test]
+    fn atomic_en_passant_explodes_around_the_target_square() {
+        let mut atomic = game(Variant::Atomic, "4k3/2n1p3/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        play(&mut atomic, "e5d6");
+        for square in ["d6", "d5", "e5", "c7"].iter() {
+            assert_eq!(piece(&atomic, square), [0, 0], "{}", square);
+        }
+        assert_eq!(piece(&atomic, "e7"), [rules::BLACK, rules::PAWN]);
+    }
+
+    #[test]
+    fn atomic_kings_next_to_each_other_are_not_in_check() {
+        let atomic = game(Variant::Atomic, "8/8/8/8/8/3k4/3K3r/8 w - - 0 1");
+        assert_eq!(atomic.status(), Status::InProgress);
+        assert_eq!(game(Variant::Standard, "8/8/8/8/8/3k4/3K3r/8 w - - 0 1").status(), Status::Check);
+        // A king may not capture, since it would explode with the captured piece.
+        let king_takes = game(Variant::Atomic, "4k3/8/8/8/8/8/3p4/4K3 w - - 0 1");
+        assert!(king_takes.position.parse_uci("e1d2").is_some_and(|mv| !king_takes.legal_moves().contains(&mv)));
+    }
+
+    #[test]
+    fn atomic_explosions_take_castling_rights() {
+        let mut atomic = game(Variant::Atomic, "r3k2r/6n1/8/8/8/8/1B6/R3K2R w KQkq - 0 1");
+        play(&mut atomic, "b2g7");
+        assert_eq!(atomic.position.castling[rules::BLACK_KING_SIDE], None);
+        assert!(atomic.position.castling[rules::BLACK_QUEEN_SIDE].is_some());
+        assert!(atomic.position.castling[rules::WHITE_KING_SIDE].is_some());
+
+        let mut atomic = game(Variant::Atomic, "r3k2r/3n4/8/8/8/8/8/R2QK2R w KQkq - 0 1");
+        assert_eq!(play(&mut atomic, "d1d7"), "Qxd7#");
+        assert_eq!(atomic.position.castling[rules::BLACK_KING_SIDE], None);
+        assert_eq!(atomic.position.castling[rules::BLACK_QUEEN_SIDE], None);
+        assert_eq!(atomic.winner(), Some(rules::WHITE));
+        assert_eq!(atomic.state_text(), "White wins by explosion");
+    }
+
+    #[test]
+    fn king_of_the_hill_is_won_on_a_centre_square() {
+        let mut hill = game(Variant::KingOfTheHill, "8/8/8/8/8/4K3/8/k7 w - - 0 1");
+        assert_eq!(play(&mut hill, "e3e4"), "Ke4#");
+        assert_eq!(hill.winner(), Some(rules::WHITE));
+        assert_eq!(hill.result(), "1-0");
+        assert!(hill.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn three_check_is_won_on_the_third_check() {
+        let mut three = game(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K2R w - - 0 1");
+        assert_eq!(play(&mut three, "h1h8"), "Rh8+");
+        assert_eq!(three.checks, [1, 0]);
+        assert_eq!(three.winner(), None);
+
+        let mut three = game(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K2R w - - 0 1");
+        three.checks = [2, 0];
+        assert_eq!(play(&mut three, "h1h8"), "Rh8#");
+        assert_eq!(three.winner(), Some(rules::WHITE));
+        assert_eq!(three.state_text(), "White wins by three checks");
+    }
+}